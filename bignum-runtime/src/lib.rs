@@ -0,0 +1,730 @@
+//! Bignum runtime implementing the part of libtommath's C ABI the generator
+//! calls. Numbers keep libtommath's `mp_int` layout with 60 bit digits, so
+//! generated code and the host side work the same with either library.
+
+use std::cmp::Ordering;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+pub type MpDigit = u64;
+
+pub const MP_DIGIT_BIT: u32 = 60;
+const MP_PREC: usize = 8;
+
+pub const MP_OKAY: c_int = 0;
+pub const MP_MEM: c_int = -2;
+pub const MP_VAL: c_int = -3;
+
+pub const MP_LT: c_int = -1;
+pub const MP_EQ: c_int = 0;
+pub const MP_GT: c_int = 1;
+
+pub const MP_ZPOS: c_int = 0;
+pub const MP_NEG: c_int = 1;
+
+const RADIX_MAP: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz+/";
+
+#[repr(C)]
+pub struct MpInt {
+    pub used: c_int,
+    pub alloc: c_int,
+    pub sign: c_int,
+    pub dp: *mut MpDigit,
+}
+
+extern "C" {
+    fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void;
+    fn free(ptr: *mut c_void);
+}
+
+/// Sign and magnitude, the magnitude being little endian 32 bit limbs
+/// without leading zeros. All arithmetic is done on this form.
+struct Num {
+    neg: bool,
+    mag: Vec<u32>,
+}
+
+impl Num {
+    fn new(neg: bool, mag: Vec<u32>) -> Num {
+        let mag = trim(mag);
+        Num {
+            neg: neg && !mag.is_empty(),
+            mag,
+        }
+    }
+}
+
+fn trim(mut mag: Vec<u32>) -> Vec<u32> {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+    mag
+}
+
+fn repack(src: &[u64], src_bits: u32, dst_bits: u32) -> Vec<u64> {
+    let mask = (1u128 << dst_bits) - 1;
+    let mut dst = Vec::with_capacity(src.len() * src_bits as usize / dst_bits as usize + 1);
+    let mut acc: u128 = 0;
+    let mut acc_bits = 0;
+    for &digit in src {
+        acc |= u128::from(digit) << acc_bits;
+        acc_bits += src_bits;
+        while acc_bits >= dst_bits {
+            dst.push((acc & mask) as u64);
+            acc >>= dst_bits;
+            acc_bits -= dst_bits;
+        }
+    }
+    if acc_bits > 0 {
+        dst.push(acc as u64);
+    }
+    while dst.last() == Some(&0) {
+        dst.pop();
+    }
+    dst
+}
+
+unsafe fn load(a: *const MpInt) -> Num {
+    let a = &*a;
+    let digits = if a.used > 0 {
+        std::slice::from_raw_parts(a.dp, a.used as usize)
+    } else {
+        &[]
+    };
+    let mag = repack(digits, MP_DIGIT_BIT, 32)
+        .into_iter()
+        .map(|limb| limb as u32)
+        .collect();
+    Num::new(a.sign == MP_NEG, mag)
+}
+
+unsafe fn grow(a: *mut MpInt, size: usize) -> c_int {
+    let a = &mut *a;
+    if (a.alloc as usize) < size || a.dp.is_null() {
+        let size = size.max(MP_PREC);
+        let dp = realloc(a.dp as *mut c_void, size * std::mem::size_of::<MpDigit>());
+        if dp.is_null() {
+            return MP_MEM;
+        }
+        a.dp = dp as *mut MpDigit;
+        a.alloc = size as c_int;
+    }
+    MP_OKAY
+}
+
+unsafe fn store(a: *mut MpInt, num: &Num) -> c_int {
+    let limbs: Vec<u64> = num.mag.iter().map(|&limb| u64::from(limb)).collect();
+    let digits = repack(&limbs, 32, MP_DIGIT_BIT);
+    let res = grow(a, digits.len());
+    if res != MP_OKAY {
+        return res;
+    }
+    let a = &mut *a;
+    ptr::copy_nonoverlapping(digits.as_ptr(), a.dp, digits.len());
+    a.used = digits.len() as c_int;
+    a.sign = if num.neg { MP_NEG } else { MP_ZPOS };
+    MP_OKAY
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut res = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &limb) in long.iter().enumerate() {
+        let sum = u64::from(limb) + u64::from(*short.get(i).unwrap_or(&0)) + carry;
+        res.push(sum as u32);
+        carry = sum >> 32;
+    }
+    res.push(carry as u32);
+    trim(res)
+}
+
+/// `a - b` for `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut diff = i64::from(limb) - i64::from(*b.get(i).unwrap_or(&0)) - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        }
+        res.push(diff as u32);
+    }
+    trim(res)
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return vec![];
+    }
+    let mut res = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = u64::from(x) * u64::from(y) + u64::from(res[i + j]) + carry;
+            res[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        res[i + b.len()] = carry as u32;
+    }
+    trim(res)
+}
+
+fn mul_small_add(mag: &mut Vec<u32>, mul: u32, add: u32) {
+    let mut carry = u64::from(add);
+    for limb in mag.iter_mut() {
+        let t = u64::from(*limb) * u64::from(mul) + carry;
+        *limb = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        mag.push(carry as u32);
+    }
+}
+
+fn divmod_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut rem = 0u64;
+    for i in (0..a.len()).rev() {
+        let cur = (rem << 32) | u64::from(a[i]);
+        q[i] = (cur / u64::from(d)) as u32;
+        rem = cur % u64::from(d);
+    }
+    (trim(q), rem as u32)
+}
+
+fn shl_bits(a: &[u32], shift: u32) -> Vec<u32> {
+    let mut res = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u32;
+    for &limb in a {
+        if shift == 0 {
+            res.push(limb);
+        } else {
+            res.push((limb << shift) | carry);
+            carry = limb >> (32 - shift);
+        }
+    }
+    res.push(carry);
+    res
+}
+
+fn shr_bits(a: &[u32], shift: u32) -> Vec<u32> {
+    if shift == 0 {
+        return trim(a.to_vec());
+    }
+    let mut res = vec![0u32; a.len()];
+    for i in 0..a.len() {
+        let high = if i + 1 < a.len() { a[i + 1] << (32 - shift) } else { 0 };
+        res[i] = (a[i] >> shift) | high;
+    }
+    trim(res)
+}
+
+/// Long division (Knuth's algorithm D), `b` must not be zero.
+fn divmod_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(a, b) == Ordering::Less {
+        return (vec![], a.to_vec());
+    }
+    if b.len() == 1 {
+        let (q, r) = divmod_small(a, b[0]);
+        return (q, trim(vec![r]));
+    }
+    let shift = b[b.len() - 1].leading_zeros();
+    let n = b.len();
+    let m = a.len() - n;
+    let vn = shl_bits(b, shift);
+    let mut un = shl_bits(a, shift);
+    let mut q = vec![0u32; m + 1];
+    let base = 1u64 << 32;
+    for j in (0..=m).rev() {
+        let num = (u64::from(un[j + n]) << 32) | u64::from(un[j + n - 1]);
+        let mut qhat = num / u64::from(vn[n - 1]);
+        let mut rhat = num % u64::from(vn[n - 1]);
+        while qhat >= base || qhat * u64::from(vn[n - 2]) > ((rhat << 32) | u64::from(un[j + n - 2]))
+        {
+            qhat -= 1;
+            rhat += u64::from(vn[n - 1]);
+            if rhat >= base {
+                break;
+            }
+        }
+        let mut k = 0i64;
+        for i in 0..n {
+            let p = qhat * u64::from(vn[i]);
+            let t = i64::from(un[i + j]) - k - (p & 0xffff_ffff) as i64;
+            un[i + j] = t as u32;
+            k = (p >> 32) as i64 - (t >> 32);
+        }
+        let t = i64::from(un[j + n]) - k;
+        un[j + n] = t as u32;
+        if t < 0 {
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let sum = u64::from(un[i + j]) + u64::from(vn[i]) + carry;
+                un[i + j] = sum as u32;
+                carry = sum >> 32;
+            }
+            un[j + n] = un[j + n].wrapping_add(carry as u32);
+        }
+        q[j] = qhat as u32;
+    }
+    (trim(q), shr_bits(&un[..n], shift))
+}
+
+fn add_num(a: &Num, b: &Num) -> Num {
+    if a.neg == b.neg {
+        return Num::new(a.neg, add_mag(&a.mag, &b.mag));
+    }
+    match cmp_mag(&a.mag, &b.mag) {
+        Ordering::Less => Num::new(b.neg, sub_mag(&b.mag, &a.mag)),
+        _ => Num::new(a.neg, sub_mag(&a.mag, &b.mag)),
+    }
+}
+
+fn cmp_num(a: &Num, b: &Num) -> Ordering {
+    match (a.neg, b.neg) {
+        (false, true) => Ordering::Greater,
+        (true, false) => Ordering::Less,
+        (false, false) => cmp_mag(&a.mag, &b.mag),
+        (true, true) => cmp_mag(&b.mag, &a.mag),
+    }
+}
+
+fn to_radix(num: &Num, radix: u32) -> Vec<u8> {
+    if num.mag.is_empty() {
+        return vec![b'0'];
+    }
+    let mut digits = vec![];
+    let mut mag = num.mag.clone();
+    while !mag.is_empty() {
+        let (q, r) = divmod_small(&mag, radix);
+        digits.push(RADIX_MAP[r as usize]);
+        mag = q;
+    }
+    if num.neg {
+        digits.push(b'-');
+    }
+    digits.reverse();
+    digits
+}
+
+fn valid_radix(radix: c_int) -> bool {
+    (2..=64).contains(&radix)
+}
+
+/// # Safety
+///
+/// `a` must point to writable memory for an `MpInt`; what it held is
+/// overwritten without being freed.
+#[no_mangle]
+pub unsafe extern "C" fn mp_init(a: *mut MpInt) -> c_int {
+    (*a).dp = ptr::null_mut();
+    (*a).alloc = 0;
+    (*a).used = 0;
+    (*a).sign = MP_ZPOS;
+    grow(a, MP_PREC)
+}
+
+/// # Safety
+///
+/// `a` must point to an `MpInt` initialized by `mp_init` or cleared
+/// already.
+#[no_mangle]
+pub unsafe extern "C" fn mp_clear(a: *mut MpInt) {
+    if !(*a).dp.is_null() {
+        free((*a).dp as *mut c_void);
+    }
+    (*a).dp = ptr::null_mut();
+    (*a).alloc = 0;
+    (*a).used = 0;
+    (*a).sign = MP_ZPOS;
+}
+
+/// # Safety
+///
+/// `a` and `b` must point to initialized `MpInt`s.
+#[no_mangle]
+pub unsafe extern "C" fn mp_copy(a: *const MpInt, b: *mut MpInt) -> c_int {
+    store(b, &load(a))
+}
+
+/// # Safety
+///
+/// `a` must point to writable memory for an `MpInt`, as for `mp_init`, and
+/// `b` to an initialized one.
+#[no_mangle]
+pub unsafe extern "C" fn mp_init_copy(a: *mut MpInt, b: *const MpInt) -> c_int {
+    let res = mp_init(a);
+    if res != MP_OKAY {
+        return res;
+    }
+    mp_copy(b, a)
+}
+
+/// # Safety
+///
+/// `a` must point to an initialized `MpInt`.
+#[no_mangle]
+pub unsafe extern "C" fn mp_set_long_long(a: *mut MpInt, b: u64) -> c_int {
+    store(a, &Num::new(false, vec![b as u32, (b >> 32) as u32]))
+}
+
+/// Lowest 64 bits of the magnitude.
+///
+/// # Safety
+///
+/// `a` must point to an initialized `MpInt`.
+#[no_mangle]
+pub unsafe extern "C" fn mp_get_long_long(a: *const MpInt) -> u64 {
+    let mag = load(a).mag;
+    let limb = |i: usize| u64::from(mag.get(i).cloned().unwrap_or(0));
+    limb(0) | limb(1) << 32
+}
+
+/// # Safety
+///
+/// `a` must point to an initialized `MpInt` and `s` to a NUL terminated
+/// string.
+#[no_mangle]
+pub unsafe extern "C" fn mp_read_radix(a: *mut MpInt, s: *const c_char, radix: c_int) -> c_int {
+    if !valid_radix(radix) {
+        return MP_VAL;
+    }
+    let mut p = s as *const u8;
+    let neg = *p == b'-';
+    if neg {
+        p = p.add(1);
+    }
+    let mut mag = vec![];
+    loop {
+        let mut ch = *p;
+        if radix <= 36 {
+            ch = ch.to_ascii_uppercase();
+        }
+        match RADIX_MAP.iter().position(|&c| c == ch) {
+            Some(pos) if (pos as c_int) < radix => mul_small_add(&mut mag, radix as u32, pos as u32),
+            _ => break,
+        }
+        p = p.add(1);
+    }
+    if *p != 0 && *p != b'\r' && *p != b'\n' {
+        store(a, &Num::new(false, vec![]));
+        return MP_VAL;
+    }
+    store(a, &Num::new(neg, mag))
+}
+
+/// # Safety
+///
+/// `a` must point to an initialized `MpInt` and `size` to a writable
+/// `int`.
+#[no_mangle]
+pub unsafe extern "C" fn mp_radix_size(a: *const MpInt, radix: c_int, size: *mut c_int) -> c_int {
+    *size = 0;
+    if !valid_radix(radix) {
+        return MP_VAL;
+    }
+    *size = to_radix(&load(a), radix as u32).len() as c_int + 1;
+    MP_OKAY
+}
+
+/// # Safety
+///
+/// `a` must point to an initialized `MpInt` and `s` to at least as many
+/// bytes as `mp_radix_size` gives for it in `radix`.
+#[no_mangle]
+pub unsafe extern "C" fn mp_toradix(a: *const MpInt, s: *mut c_char, radix: c_int) -> c_int {
+    if !valid_radix(radix) {
+        return MP_VAL;
+    }
+    let digits = to_radix(&load(a), radix as u32);
+    ptr::copy_nonoverlapping(digits.as_ptr(), s as *mut u8, digits.len());
+    *s.add(digits.len()) = 0;
+    MP_OKAY
+}
+
+/// # Safety
+///
+/// `a`, `b` and `c` must point to initialized `MpInt`s, `c` may be either
+/// operand.
+#[no_mangle]
+pub unsafe extern "C" fn mp_add(a: *const MpInt, b: *const MpInt, c: *mut MpInt) -> c_int {
+    store(c, &add_num(&load(a), &load(b)))
+}
+
+/// # Safety
+///
+/// As for `mp_add`.
+#[no_mangle]
+pub unsafe extern "C" fn mp_sub(a: *const MpInt, b: *const MpInt, c: *mut MpInt) -> c_int {
+    let mut b = load(b);
+    b.neg = !b.neg && !b.mag.is_empty();
+    store(c, &add_num(&load(a), &b))
+}
+
+/// # Safety
+///
+/// As for `mp_add`.
+#[no_mangle]
+pub unsafe extern "C" fn mp_mul(a: *const MpInt, b: *const MpInt, c: *mut MpInt) -> c_int {
+    let (a, b) = (load(a), load(b));
+    store(c, &Num::new(a.neg != b.neg, mul_mag(&a.mag, &b.mag)))
+}
+
+/// Quotient rounded towards zero into `c` and remainder with the sign of `a`
+/// into `d`, either may be null.
+///
+/// # Safety
+///
+/// `a` and `b` must point to initialized `MpInt`s, and `c` and `d` to
+/// initialized ones unless null.
+#[no_mangle]
+pub unsafe extern "C" fn mp_div(
+    a: *const MpInt,
+    b: *const MpInt,
+    c: *mut MpInt,
+    d: *mut MpInt,
+) -> c_int {
+    let (a, b) = (load(a), load(b));
+    if b.mag.is_empty() {
+        return MP_VAL;
+    }
+    let (q, r) = divmod_mag(&a.mag, &b.mag);
+    if !c.is_null() {
+        let res = store(c, &Num::new(a.neg != b.neg, q));
+        if res != MP_OKAY {
+            return res;
+        }
+    }
+    if !d.is_null() {
+        return store(d, &Num::new(a.neg, r));
+    }
+    MP_OKAY
+}
+
+/// # Safety
+///
+/// `a` and `b` must point to initialized `MpInt`s, possibly the same.
+#[no_mangle]
+pub unsafe extern "C" fn mp_neg(a: *const MpInt, b: *mut MpInt) -> c_int {
+    let a = load(a);
+    store(b, &Num::new(!a.neg, a.mag))
+}
+
+/// # Safety
+///
+/// `a` and `b` must point to initialized `MpInt`s.
+#[no_mangle]
+pub unsafe extern "C" fn mp_cmp(a: *const MpInt, b: *const MpInt) -> c_int {
+    match cmp_num(&load(a), &load(b)) {
+        Ordering::Less => MP_LT,
+        Ordering::Equal => MP_EQ,
+        Ordering::Greater => MP_GT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{quickcheck, Arbitrary, Gen, TestResult};
+    use std::ffi::{CStr, CString};
+
+    /// `i128` of any width up to 126 bits, so that sums stay in range.
+    #[derive(Debug, Clone, Copy)]
+    struct Wide(i128);
+
+    impl Arbitrary for Wide {
+        fn arbitrary<G: Gen>(g: &mut G) -> Wide {
+            let bits = g.next_u32() % 127;
+            let random = (u128::from(g.next_u64()) << 64) | u128::from(g.next_u64());
+            let magnitude = if bits == 0 {
+                0
+            } else {
+                (random >> (128 - bits)) as i128
+            };
+            Wide(if g.next_u32() & 1 == 0 {
+                magnitude
+            } else {
+                -magnitude
+            })
+        }
+    }
+
+    /// Magnitude of up to 12 full range limbs, leading zeros included.
+    #[derive(Debug, Clone)]
+    struct Limbs(Vec<u32>);
+
+    impl Arbitrary for Limbs {
+        fn arbitrary<G: Gen>(g: &mut G) -> Limbs {
+            let len = g.next_u32() % 13;
+            Limbs(
+                (0..len)
+                    .map(|_| match g.next_u32() % 4 {
+                        // extremes exercise the corrections of the quotient estimate
+                        0 => 0,
+                        1 => u32::MAX,
+                        2 => 1 << 31,
+                        _ => g.next_u32(),
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    /// `MpInt` cleared when dropped.
+    struct Big(MpInt);
+
+    impl Big {
+        fn new() -> Big {
+            let mut raw = MpInt {
+                used: 0,
+                alloc: 0,
+                sign: MP_ZPOS,
+                dp: ptr::null_mut(),
+            };
+            assert_eq!(unsafe { mp_init(&mut raw) }, MP_OKAY);
+            Big(raw)
+        }
+
+        fn parse(s: &str, radix: c_int) -> Big {
+            let mut num = Big::new();
+            let s = CString::new(s).unwrap();
+            assert_eq!(
+                unsafe { mp_read_radix(&mut num.0, s.as_ptr(), radix) },
+                MP_OKAY
+            );
+            num
+        }
+
+        fn from_i128(value: i128) -> Big {
+            Big::parse(&value.to_string(), 10)
+        }
+
+        fn to_str_radix(&self, radix: c_int) -> String {
+            let mut size = 0;
+            assert_eq!(unsafe { mp_radix_size(&self.0, radix, &mut size) }, MP_OKAY);
+            let mut buf = vec![0u8; size as usize];
+            assert_eq!(
+                unsafe { mp_toradix(&self.0, buf.as_mut_ptr() as *mut c_char, radix) },
+                MP_OKAY
+            );
+            let s = CStr::from_bytes_with_nul(&buf).unwrap();
+            s.to_str().unwrap().to_owned()
+        }
+
+        fn to_i128(&self) -> i128 {
+            self.to_str_radix(10).parse().unwrap()
+        }
+    }
+
+    impl Drop for Big {
+        fn drop(&mut self) {
+            unsafe { mp_clear(&mut self.0) }
+        }
+    }
+
+    type BinaryOp = unsafe extern "C" fn(*const MpInt, *const MpInt, *mut MpInt) -> c_int;
+
+    fn apply(op: BinaryOp, a: i128, b: i128) -> i128 {
+        let (a, b, mut c) = (Big::from_i128(a), Big::from_i128(b), Big::new());
+        assert_eq!(unsafe { op(&a.0, &b.0, &mut c.0) }, MP_OKAY);
+        c.to_i128()
+    }
+
+    fn divmod(a: i128, b: i128) -> (i128, i128) {
+        let (a, b) = (Big::from_i128(a), Big::from_i128(b));
+        let (mut q, mut r) = (Big::new(), Big::new());
+        assert_eq!(unsafe { mp_div(&a.0, &b.0, &mut q.0, &mut r.0) }, MP_OKAY);
+        (q.to_i128(), r.to_i128())
+    }
+
+    fn matches_i128(a: Wide, b: Wide) -> bool {
+        let (a, b) = (a.0, b.0);
+        let cmp = unsafe { mp_cmp(&Big::from_i128(a).0, &Big::from_i128(b).0) };
+        apply(mp_add, a, b) == a + b
+            && apply(mp_sub, a, b) == a - b
+            && a.checked_mul(b).is_none_or(|p| apply(mp_mul, a, b) == p)
+            && (b == 0 || divmod(a, b) == (a / b, a % b))
+            && cmp == a.cmp(&b) as c_int
+    }
+
+    fn divmod_round_trips(a: Limbs, b: Limbs) -> TestResult {
+        let (a, b) = (trim(a.0), trim(b.0));
+        if b.is_empty() {
+            return TestResult::discard();
+        }
+        let (q, r) = divmod_mag(&a, &b);
+        TestResult::from_bool(
+            add_mag(&mul_mag(&q, &b), &r) == a && cmp_mag(&r, &b) == Ordering::Less,
+        )
+    }
+
+    #[test]
+    fn arithmetic_matches_i128() {
+        quickcheck(matches_i128 as fn(Wide, Wide) -> bool);
+    }
+
+    #[test]
+    fn division_round_trips() {
+        quickcheck(divmod_round_trips as fn(Limbs, Limbs) -> TestResult);
+    }
+
+    #[test]
+    fn divides_large_operands() {
+        // 3^400 by 7^150 + 2^64, both many limbs long
+        let pow = |base: &str, exp| {
+            let (base, mut res) = (Big::parse(base, 10), Big::parse("1", 10));
+            for _ in 0..exp {
+                let mut next = Big::new();
+                assert_eq!(unsafe { mp_mul(&res.0, &base.0, &mut next.0) }, MP_OKAY);
+                res = next;
+            }
+            res
+        };
+        let a = pow("3", 400);
+        let mut b = Big::new();
+        let two_64 = Big::parse("18446744073709551616", 10);
+        assert_eq!(
+            unsafe { mp_add(&pow("7", 150).0, &two_64.0, &mut b.0) },
+            MP_OKAY
+        );
+        let (mut q, mut r, mut product, mut sum) = (Big::new(), Big::new(), Big::new(), Big::new());
+        unsafe {
+            assert_eq!(mp_div(&a.0, &b.0, &mut q.0, &mut r.0), MP_OKAY);
+            assert_eq!(mp_mul(&q.0, &b.0, &mut product.0), MP_OKAY);
+            assert_eq!(mp_add(&product.0, &r.0, &mut sum.0), MP_OKAY);
+            assert_eq!(mp_cmp(&sum.0, &a.0), MP_EQ);
+            assert_eq!(mp_cmp(&r.0, &b.0), MP_LT);
+        }
+        assert_eq!(sum.to_str_radix(10), a.to_str_radix(10));
+    }
+
+    #[test]
+    fn converts_radixes() {
+        for &value in [0i128, 1, -1, 255, -(1 << 100) - 12345, i128::MAX].iter() {
+            let num = Big::from_i128(value);
+            let sign = if value < 0 { "-" } else { "" };
+            let magnitude = value.unsigned_abs();
+            assert_eq!(num.to_str_radix(2), format!("{}{:b}", sign, magnitude));
+            assert_eq!(num.to_str_radix(8), format!("{}{:o}", sign, magnitude));
+            assert_eq!(num.to_str_radix(16), format!("{}{:X}", sign, magnitude));
+            for radix in 2..=64 {
+                let text = num.to_str_radix(radix);
+                assert_eq!(Big::parse(&text, radix).to_i128(), value, "{}", text);
+            }
+        }
+        let mut num = Big::new();
+        let s = CString::new("12a").unwrap();
+        assert_eq!(unsafe { mp_read_radix(&mut num.0, s.as_ptr(), 10) }, MP_VAL);
+        assert_eq!(unsafe { mp_read_radix(&mut num.0, s.as_ptr(), 65) }, MP_VAL);
+    }
+}
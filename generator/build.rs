@@ -1,4 +1,103 @@
+extern crate cc;
+extern crate lalrpop;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
 fn main() {
-    println!("cargo:rustc-link-search=/Users/jinnzest/Documents/nulljinn/tests/libtommath/");
-    println!("cargo:rustc-link-lib=static=tommath");
+    if env::var_os("CARGO_FEATURE_RUST_BIGNUM").is_none() {
+        build_libtommath();
+    } else {
+        export_bignum_runtime_dir();
+    }
+    build_input_runtime();
+    lalrpop::process_root().unwrap();
+}
+
+/// Compiles the input runtime which, like libtommath, is linked into the
+/// generator and, through `CALC_RUNTIME_LIB_DIR`, into AOT executables.
+fn build_input_runtime() {
+    println!("cargo:rerun-if-changed=runtime/input.c");
+    cc::Build::new()
+        .file("runtime/input.c")
+        .opt_level(2)
+        .compile("calc_input");
+
+    println!(
+        "cargo:rustc-env=CALC_RUNTIME_LIB_DIR={}",
+        env::var("OUT_DIR").unwrap()
+    );
+}
+
+/// Exports as `BIGNUM_RUNTIME_DEPS_DIR` the directory cargo builds the
+/// dependencies into, where the static library of the bignum runtime crate
+/// that AOT executables are linked with ends up.
+fn export_bignum_runtime_dir() {
+    // OUT_DIR is <target dir>/<profile>/build/<package>-<hash>/out
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let deps_dir = out_dir.ancestors().nth(3).unwrap().join("deps");
+    println!(
+        "cargo:rustc-env=BIGNUM_RUNTIME_DEPS_DIR={}",
+        deps_dir.display()
+    );
+}
+
+/// Compiles the libtommath submodule into a static library which is linked
+/// into the generator and, through `TOMMATH_LIB_DIR`, into AOT executables.
+fn build_libtommath() {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let tommath_dir = manifest_dir.join("../libtommath");
+    println!("cargo:rerun-if-changed={}", tommath_dir.display());
+
+    let sources: Vec<PathBuf> = match fs::read_dir(&tommath_dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "c"))
+            .collect(),
+        Err(_) => vec![],
+    };
+    if sources.is_empty() {
+        panic!(
+            "no libtommath sources found in {}, run `git submodule update --init` \
+             or build with `--features rust-bignum`",
+            tommath_dir.display()
+        );
+    }
+    // later releases drop the first two and take a `size_t` size in the last
+    let header = fs::read_to_string(tommath_dir.join("tommath.h")).unwrap_or_default();
+    let api = [
+        (
+            "mp_toradix",
+            "mp_toradix(const mp_int *a, char *str, int radix)",
+        ),
+        ("mp_get_long_long", "mp_get_long_long(const mp_int *a)"),
+        (
+            "mp_radix_size",
+            "mp_radix_size(const mp_int *a, int radix, int *size)",
+        ),
+    ];
+    for &(func, declaration) in api.iter() {
+        if !header.contains(declaration) {
+            panic!(
+                "libtommath in {} lacks `{}` as the generator declares it, \
+                 check out release 1.1.0",
+                tommath_dir.display(),
+                func
+            );
+        }
+    }
+
+    cc::Build::new()
+        .files(sources)
+        .include(&tommath_dir)
+        .opt_level(2)
+        .warnings(false)
+        .compile("tommath");
+
+    println!(
+        "cargo:rustc-env=TOMMATH_LIB_DIR={}",
+        env::var("OUT_DIR").unwrap()
+    );
 }
@@ -0,0 +1,111 @@
+/*
+ * Input runtime of the generated code: reads whitespace delimited tokens and
+ * lines of any length, up to a bound, into heap buffers grown as needed.
+ *
+ * Every function returns one of the statuses below, the failures being
+ * negative and apart from libtommath's codes. On success `*text` is a NUL
+ * terminated buffer the caller frees, `*len` its length without the NUL.
+ */
+
+#include <ctype.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#define CALC_INPUT_OK 0
+#define CALC_INPUT_ERROR -101
+#define CALC_INPUT_MEM -102
+#define CALC_INPUT_TOO_LONG -103
+#define CALC_INPUT_EOF -104
+
+#define CALC_INPUT_INITIAL_SIZE 32
+
+/* Stores characters of `file` from `c` on until `is_end` accepts one, which
+ * is consumed, or the end of the file. */
+static int read_until(FILE *file, int c, int (*is_end)(int), size_t max_len, char **text,
+                      size_t *len)
+{
+    size_t size = CALC_INPUT_INITIAL_SIZE;
+    size_t used = 0;
+    char *buf = malloc(size);
+
+    if (buf == NULL) {
+        return CALC_INPUT_MEM;
+    }
+    while (c != EOF && !is_end(c)) {
+        if (used == max_len) {
+            /* drops the rest so that the next read starts after it */
+            while (c != EOF && !is_end(c)) {
+                c = getc(file);
+            }
+            free(buf);
+            return CALC_INPUT_TOO_LONG;
+        }
+        if (used + 1 == size) {
+            char *grown = realloc(buf, size * 2);
+            if (grown == NULL) {
+                free(buf);
+                return CALC_INPUT_MEM;
+            }
+            buf = grown;
+            size *= 2;
+        }
+        buf[used++] = (char)c;
+        c = getc(file);
+    }
+    if (c == EOF && ferror(file)) {
+        free(buf);
+        return CALC_INPUT_ERROR;
+    }
+    buf[used] = '\0';
+    *text = buf;
+    *len = used;
+    return CALC_INPUT_OK;
+}
+
+static int is_space(int c)
+{
+    return isspace(c);
+}
+
+static int is_newline(int c)
+{
+    return c == '\n';
+}
+
+/* Reads the next token of `file`, the standard input when NULL, of at most
+ * `max_len` characters. */
+int calc_read_token(FILE *file, size_t max_len, char **text, size_t *len)
+{
+    int c;
+
+    if (file == NULL) {
+        file = stdin;
+    }
+    *text = NULL;
+    *len = 0;
+    do {
+        c = getc(file);
+    } while (c != EOF && isspace(c));
+    if (c == EOF) {
+        return ferror(file) ? CALC_INPUT_ERROR : CALC_INPUT_EOF;
+    }
+    return read_until(file, c, is_space, max_len, text, len);
+}
+
+/* Reads the rest of the current line of `file`, the standard input when
+ * NULL, without its newline, of at most `max_len` characters. */
+int calc_read_line(FILE *file, size_t max_len, char **text, size_t *len)
+{
+    int c;
+
+    if (file == NULL) {
+        file = stdin;
+    }
+    *text = NULL;
+    *len = 0;
+    c = getc(file);
+    if (c == EOF) {
+        return ferror(file) ? CALC_INPUT_ERROR : CALC_INPUT_EOF;
+    }
+    return read_until(file, c, is_newline, max_len, text, len);
+}
@@ -0,0 +1,480 @@
+use bignumloader::BigInt;
+use calc;
+use lalrpop_util::ParseError;
+use std::fmt;
+
+/// Byte range of a construct in the source text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// One based line and column of the start of the span.
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let col = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        (line, col)
+    }
+}
+
+/// Types of values, named like in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    /// 64 bit signed integer, overflowing is an error.
+    Int,
+    /// Arbitrary precision integer.
+    BigInt,
+    Str,
+    Bool,
+}
+
+impl Type {
+    pub fn is_numeric(self) -> bool {
+        self == Type::Int || self == Type::BigInt
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Type::Int => "int",
+            Type::BigInt => "bigint",
+            Type::Str => "string",
+            Type::Bool => "bool",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Rem => "%",
+        }
+    }
+}
+
+/// Name of a variable where it appears in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CmpOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CmpOp::Eq => "==",
+            CmpOp::Ne => "!=",
+            CmpOp::Lt => "<",
+            CmpOp::Le => "<=",
+            CmpOp::Gt => ">",
+            CmpOp::Ge => ">=",
+        }
+    }
+}
+
+/// Short-circuiting boolean operators.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicOp {
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Num(BigInt, Span),
+    Str(String, Span),
+    Bool(bool, Span),
+    Var(Ident),
+    Call(Ident, Vec<Expr>, Span),
+    /// Reads a number from the standard input.
+    Read(Span),
+    /// Number of command line arguments, the program's name included.
+    Argc(Span),
+    /// Command line argument at an `int` index, read as a number.
+    Arg(Box<Expr>, Span),
+    Neg(Box<Expr>, Span),
+    Not(Box<Expr>, Span),
+    Binary(BinOp, Box<Expr>, Box<Expr>, Span),
+    Compare(CmpOp, Box<Expr>, Box<Expr>, Span),
+    Logic(LogicOp, Box<Expr>, Box<Expr>, Span),
+    Cast(Box<Expr>, Type, Span),
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match *self {
+            Expr::Num(_, span)
+            | Expr::Str(_, span)
+            | Expr::Bool(_, span)
+            | Expr::Read(span)
+            | Expr::Argc(span)
+            | Expr::Arg(_, span)
+            | Expr::Neg(_, span)
+            | Expr::Not(_, span)
+            | Expr::Binary(_, _, _, span)
+            | Expr::Compare(_, _, _, span)
+            | Expr::Logic(_, _, _, span)
+            | Expr::Cast(_, _, span)
+            | Expr::Call(_, _, span) => span,
+            Expr::Var(ref ident) => ident.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Print(Expr, Span),
+    /// Declares a variable in the innermost block, the type defaults to
+    /// the one of the initializer.
+    Let(Ident, Option<Type>, Expr),
+    Assign(Ident, Expr),
+    Block(Vec<Stmt>),
+    /// The else branch is empty when there is none.
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    /// Runs the body with the variable going from the first value up to,
+    /// excluding, the second one.
+    For(Ident, Expr, Expr, Vec<Stmt>),
+    Break(Span),
+    Continue(Span),
+    Return(Option<Expr>, Span),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone)]
+pub struct FnDecl {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    /// `None` for a function returning nothing.
+    pub ret: Option<Type>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// Top level declaration or statement.
+#[derive(Debug, Clone)]
+pub enum Item {
+    Fn(FnDecl),
+    Stmt(Stmt),
+}
+
+/// Functions and the top level statements, which make up `main`.
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub functions: Vec<FnDecl>,
+    pub stmts: Vec<Stmt>,
+}
+
+impl Program {
+    pub fn new(items: Vec<Item>) -> Program {
+        let mut program = Program {
+            functions: vec![],
+            stmts: vec![],
+        };
+        for item in items {
+            match item {
+                Item::Fn(decl) => program.functions.push(decl),
+                Item::Stmt(stmt) => program.stmts.push(stmt),
+            }
+        }
+        program
+    }
+}
+
+/// Error found in the source text, reported as `line:col: message`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl SourceError {
+    pub fn new(source: &str, span: Span, message: String) -> SourceError {
+        let (line, col) = span.line_col(source);
+        SourceError { line, col, message }
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+/// Contents of a string literal given with its quotes.
+pub fn unescape(literal: &str) -> Result<String, &'static str> {
+    let mut res = String::new();
+    let mut chars = literal[1..literal.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('\\') => res.push('\\'),
+            Some('"') => res.push('"'),
+            _ => return Err("unknown escape sequence in string literal"),
+        }
+    }
+    Ok(res)
+}
+
+pub fn parse(source: &str) -> Result<Program, SourceError> {
+    calc::ProgramParser::new()
+        .parse(source)
+        .map_err(|e| parse_error(source, e))
+}
+
+/// Whether `source` fails to parse only because it ends too early, so more
+/// lines may complete it.
+pub fn is_incomplete(source: &str) -> bool {
+    matches!(
+        calc::ProgramParser::new().parse(source),
+        Err(ParseError::UnrecognizedEOF { .. })
+    )
+}
+
+fn parse_error<T: fmt::Display>(
+    source: &str,
+    e: ParseError<usize, T, (Span, &str)>,
+) -> SourceError {
+    let (span, message) = match e {
+        ParseError::InvalidToken { location } => {
+            (Span::new(location, location), "invalid token".to_owned())
+        }
+        ParseError::UnrecognizedEOF { location, expected } => (
+            Span::new(location, location),
+            format!("unexpected end of input, expected {}", expected.join(", ")),
+        ),
+        ParseError::UnrecognizedToken {
+            token: (start, token, end),
+            expected,
+        } => (
+            Span::new(start, end),
+            format!("unexpected `{}`, expected {}", token, expected.join(", ")),
+        ),
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => (Span::new(start, end), format!("unexpected `{}`", token)),
+        ParseError::User {
+            error: (span, message),
+        } => (span, message.to_owned()),
+    };
+    SourceError::new(source, span, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_expr(source: &str) -> Expr {
+        let program = parse(&format!("print {};", source)).unwrap();
+        match program.stmts.into_iter().next() {
+            Some(Stmt::Print(expr, _)) => expr,
+            other => panic!("expected a print statement, got {:?}", other),
+        }
+    }
+
+    fn show(expr: &Expr) -> String {
+        match *expr {
+            Expr::Num(ref n, _) => n.to_string(),
+            Expr::Str(ref s, _) => format!("{:?}", s),
+            Expr::Bool(b, _) => b.to_string(),
+            Expr::Var(ref ident) => ident.name.clone(),
+            Expr::Call(ref ident, ref args, _) => {
+                let args: Vec<String> = args.iter().map(show).collect();
+                format!("{}({})", ident.name, args.join(", "))
+            }
+            Expr::Read(_) => "read".to_owned(),
+            Expr::Argc(_) => "argc".to_owned(),
+            Expr::Arg(ref e, _) => format!("arg({})", show(e)),
+            Expr::Neg(ref e, _) => format!("(-{})", show(e)),
+            Expr::Not(ref e, _) => format!("(!{})", show(e)),
+            Expr::Binary(op, ref a, ref b, _) => format!("({:?} {} {})", op, show(a), show(b)),
+            Expr::Compare(op, ref a, ref b, _) => format!("({:?} {} {})", op, show(a), show(b)),
+            Expr::Logic(op, ref a, ref b, _) => format!("({:?} {} {})", op, show(a), show(b)),
+            Expr::Cast(ref e, ty, _) => format!("({} as {})", show(e), ty),
+        }
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(
+            show(&parse_expr("1 + 2 * 3 - 4")),
+            "(Sub (Add 1 (Mul 2 3)) 4)"
+        );
+        assert_eq!(
+            show(&parse_expr("-(1 + read) % 7")),
+            "(Rem (-(Add 1 read)) 7)"
+        );
+        assert_eq!(show(&parse_expr("8 / 4 / 2")), "(Div (Div 8 4) 2)");
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(
+            show(&parse_expr("0x1F + 0b101 + 0o17")),
+            "(Add (Add 31 5) 15)"
+        );
+        assert_eq!(
+            show(&parse_expr("123456789012345678901234567890")),
+            "123456789012345678901234567890"
+        );
+    }
+
+    #[test]
+    fn casts_strings_and_bools() {
+        assert_eq!(
+            show(&parse_expr("-1 as int * 2 as bigint")),
+            "(Mul ((-1) as int) (2 as bigint))"
+        );
+        assert_eq!(show(&parse_expr(r#""a \"b\"\n""#)), r#""a \"b\"\n""#);
+        assert_eq!(show(&parse_expr("true")), "true");
+        let e = parse(r#"print "\q";"#).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "1:7: unknown escape sequence in string literal"
+        );
+    }
+
+    #[test]
+    fn comments_and_statements() {
+        let program = parse("// sum\nprint 1 + 2; // three\n read;\n").unwrap();
+        assert_eq!(program.stmts.len(), 2);
+    }
+
+    #[test]
+    fn variables_and_blocks() {
+        let program = parse("let x: int = 1;\n{ let y = x; x = y * 2; }\nprint x_1;").unwrap();
+        match program.stmts[0] {
+            Stmt::Let(ref ident, Some(Type::Int), _) => {
+                assert_eq!(ident.name, "x");
+                assert_eq!(ident.span, Span::new(4, 5));
+            }
+            ref other => panic!("expected a declaration, got {:?}", other),
+        }
+        match program.stmts[1] {
+            Stmt::Block(ref stmts) => match stmts[1] {
+                Stmt::Assign(ref ident, ref e) => {
+                    assert_eq!(ident.name, "x");
+                    assert_eq!(show(e), "(Mul y 2)");
+                }
+                ref other => panic!("expected an assignment, got {:?}", other),
+            },
+            ref other => panic!("expected a block, got {:?}", other),
+        }
+        assert_eq!(show(&parse_expr("print_count")), "print_count");
+        assert!(parse("let print = 1;").is_err());
+    }
+
+    #[test]
+    fn functions() {
+        let program = parse(
+            "print f(1, g());\nfn f(a: int, b: bigint) -> bigint { return a * b; }\nfn g() {}",
+        )
+        .unwrap();
+        assert_eq!(program.stmts.len(), 1);
+        assert_eq!(program.functions.len(), 2);
+        let f = &program.functions[0];
+        assert_eq!(f.name.name, "f");
+        assert_eq!(f.params.len(), 2);
+        assert_eq!(
+            (f.params[1].name.name.as_str(), f.params[1].ty),
+            ("b", Type::BigInt)
+        );
+        assert_eq!(f.ret, Some(Type::BigInt));
+        assert_eq!(f.span, Span::new(17, 68));
+        match f.body[0] {
+            Stmt::Return(Some(ref e), _) => assert_eq!(show(e), "(Mul a b)"),
+            ref other => panic!("expected a return, got {:?}", other),
+        }
+        assert_eq!(program.functions[1].ret, None);
+        assert_eq!(show(&parse_expr("f(1 + 2, ) * 3")), "(Mul f((Add 1 2)) 3)");
+    }
+
+    #[test]
+    fn conditions_and_loops() {
+        assert_eq!(
+            show(&parse_expr("a < b + 1 || !c && 2 * a != 0")),
+            "(Or (Lt a (Add b 1)) (And (!c) (Ne (Mul 2 a) 0)))"
+        );
+        assert!(parse("print 1 < 2 < 3;").is_err());
+        let program = parse(
+            "if a { } else if b { break; } else { continue; }\n\
+             while x >= 1 { x = x - 1; }\n\
+             for i in 0..n + 1 { print i; }",
+        )
+        .unwrap();
+        match program.stmts[0] {
+            Stmt::If(_, ref then, ref els) => {
+                assert!(then.is_empty());
+                match els[0] {
+                    Stmt::If(ref cond, ref then, ref els) => {
+                        assert_eq!(show(cond), "b");
+                        assert_eq!(then.len(), 1);
+                        assert_eq!(els.len(), 1);
+                    }
+                    ref other => panic!("expected an else if, got {:?}", other),
+                }
+            }
+            ref other => panic!("expected an if, got {:?}", other),
+        }
+        match program.stmts[2] {
+            Stmt::For(ref var, ref from, ref to, _) => {
+                assert_eq!(var.name, "i");
+                assert_eq!(show(from), "0");
+                assert_eq!(show(to), "(Add n 1)");
+            }
+            ref other => panic!("expected a for loop, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_locations() {
+        let e = parse("print 1;\nprint (2 + ;").unwrap_err();
+        assert_eq!((e.line, e.col), (2, 12));
+        let e = parse("print 1").unwrap_err();
+        assert_eq!((e.line, e.col), (1, 8));
+    }
+}
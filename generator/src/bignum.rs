@@ -0,0 +1,391 @@
+extern crate llvm_sys;
+
+use self::llvm_sys::prelude::*;
+use bignumloader::*;
+use llvm::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BignumOp {
+    Add,
+    Sub,
+    Mul,
+    /// Quotient rounded towards zero.
+    Div,
+    /// Remainder with the sign of the dividend.
+    Rem,
+}
+
+impl BignumOp {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BignumOp::Add => "add",
+            BignumOp::Sub => "sub",
+            BignumOp::Mul => "mul",
+            BignumOp::Div => "div",
+            BignumOp::Rem => "rem",
+        }
+    }
+}
+
+/// Bignum libraries code can be generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    LibTomMath,
+}
+
+impl Backend {
+    /// Declares the functions of the library in the module of `llvm`.
+    pub fn instantiate(self, llvm: &mut LLVM) -> Box<dyn BignumBackend> {
+        match self {
+            Backend::LibTomMath => Box::new(LibTomMath::new(llvm)),
+        }
+    }
+}
+
+/// Everything code generation needs to know about a bignum library.
+///
+/// Numbers are always handled through pointers to `num_type`. Calls are
+/// built at the current position of the builder; the ones returning a
+/// value return the library's `i32` status, `ok_status` on success.
+pub trait BignumBackend {
+    fn num_type(&self) -> LLVMTypeRef;
+
+    /// Status of a call which succeeded.
+    fn ok_status(&self) -> i32;
+
+    /// Status of a call given an invalid argument.
+    fn invalid_status(&self) -> i32;
+
+    /// What `emit_cmp` returns for equal numbers.
+    fn cmp_equal(&self) -> i32;
+
+    fn emit_init(&self, llvm: &mut LLVM, num: LLVMValueRef) -> LLVMValueRef;
+
+    /// Read-only global holding `value`, usable as a source operand without
+    /// any initialization at run time. It must never be written or cleared.
+    fn emit_const(&self, llvm: &mut LLVM, name: &str, value: &BigInt) -> LLVMValueRef;
+
+    fn emit_clear(&self, llvm: &mut LLVM, num: LLVMValueRef);
+
+    fn emit_copy(&self, llvm: &mut LLVM, from: LLVMValueRef, to: LLVMValueRef) -> LLVMValueRef;
+
+    /// Sets `num` to the `i64` `value` read as unsigned.
+    fn emit_set_u64(&self, llvm: &mut LLVM, num: LLVMValueRef, value: LLVMValueRef)
+        -> LLVMValueRef;
+
+    /// Returns the lowest 64 bits of the magnitude of `num` as an `i64`.
+    fn emit_get_u64(&self, llvm: &mut LLVM, num: LLVMValueRef) -> LLVMValueRef;
+
+    /// Parses the NUL terminated string `str_ptr` written in `radix`.
+    fn emit_parse(
+        &self,
+        llvm: &mut LLVM,
+        num: LLVMValueRef,
+        str_ptr: LLVMValueRef,
+        radix: LLVMValueRef,
+    ) -> LLVMValueRef;
+
+    /// Stores into the `i32` behind `size_ref` the buffer size, including
+    /// the terminating NUL, needed by `emit_format`.
+    fn emit_format_size(
+        &self,
+        llvm: &mut LLVM,
+        num: LLVMValueRef,
+        radix: LLVMValueRef,
+        size_ref: LLVMValueRef,
+    ) -> LLVMValueRef;
+
+    fn emit_format(
+        &self,
+        llvm: &mut LLVM,
+        num: LLVMValueRef,
+        buf: LLVMValueRef,
+        radix: LLVMValueRef,
+    ) -> LLVMValueRef;
+
+    fn emit_binary(
+        &self,
+        llvm: &mut LLVM,
+        op: BignumOp,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+        res: LLVMValueRef,
+    ) -> LLVMValueRef;
+
+    fn emit_neg(&self, llvm: &mut LLVM, num: LLVMValueRef, res: LLVMValueRef) -> LLVMValueRef;
+
+    /// Returns an `i32` which is below, equal to or above `cmp_equal` like
+    /// `lhs` is to `rhs`.
+    fn emit_cmp(&self, llvm: &mut LLVM, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef;
+}
+
+pub struct LibTomMath {
+    mp_struct: LLVMTypeRef,
+    mp_init: LLVMValueRef,
+    mp_clear: LLVMValueRef,
+    mp_copy: LLVMValueRef,
+    mp_set_long_long: LLVMValueRef,
+    mp_get_long_long: LLVMValueRef,
+    mp_read_radix: LLVMValueRef,
+    mp_radix_size: LLVMValueRef,
+    mp_toradix: LLVMValueRef,
+    mp_add: LLVMValueRef,
+    mp_sub: LLVMValueRef,
+    mp_mul: LLVMValueRef,
+    mp_div: LLVMValueRef,
+    mp_neg: LLVMValueRef,
+    mp_cmp: LLVMValueRef,
+}
+
+impl LibTomMath {
+    pub fn new(llvm: &mut LLVM) -> Self {
+        let mp_struct = export_mp_struct(llvm);
+        let i32_t = llvm.i32_t();
+        let void_t = llvm.void_t();
+        let i64_t = llvm.i64_t();
+        LibTomMath {
+            mp_struct,
+            mp_init: export_mp_unary(llvm, mp_struct, "mp_init", i32_t),
+            mp_clear: export_mp_unary(llvm, mp_struct, "mp_clear", void_t),
+            mp_copy: export_mp_copy(llvm, mp_struct),
+            mp_set_long_long: export_mp_set_long_long(llvm, mp_struct),
+            mp_get_long_long: export_mp_unary(llvm, mp_struct, "mp_get_long_long", i64_t),
+            mp_read_radix: export_mp_read_radix(llvm, mp_struct),
+            mp_radix_size: export_mp_radix_size(llvm, mp_struct),
+            mp_toradix: export_mp_to_radix(llvm, mp_struct),
+            mp_add: export_mp_binary(llvm, mp_struct, "mp_add"),
+            mp_sub: export_mp_binary(llvm, mp_struct, "mp_sub"),
+            mp_mul: export_mp_binary(llvm, mp_struct, "mp_mul"),
+            mp_div: export_mp_div(llvm, mp_struct),
+            mp_neg: export_mp_neg(llvm, mp_struct),
+            mp_cmp: export_mp_cmp(llvm, mp_struct),
+        }
+    }
+}
+
+impl BignumBackend for LibTomMath {
+    fn num_type(&self) -> LLVMTypeRef {
+        self.mp_struct
+    }
+
+    fn ok_status(&self) -> i32 {
+        MP_OKAY
+    }
+
+    fn invalid_status(&self) -> i32 {
+        MP_VAL
+    }
+
+    fn cmp_equal(&self) -> i32 {
+        MP_EQ
+    }
+
+    fn emit_init(&self, llvm: &mut LLVM, num: LLVMValueRef) -> LLVMValueRef {
+        llvm.call_func("mp_init", self.mp_init, &mut vec![num])
+    }
+
+    fn emit_clear(&self, llvm: &mut LLVM, num: LLVMValueRef) {
+        llvm.call_func("", self.mp_clear, &mut vec![num]);
+    }
+
+    fn emit_copy(&self, llvm: &mut LLVM, from: LLVMValueRef, to: LLVMValueRef) -> LLVMValueRef {
+        llvm.call_func("mp_copy", self.mp_copy, &mut vec![from, to])
+    }
+
+    fn emit_set_u64(
+        &self,
+        llvm: &mut LLVM,
+        num: LLVMValueRef,
+        value: LLVMValueRef,
+    ) -> LLVMValueRef {
+        llvm.call_func(
+            "mp_set_long_long",
+            self.mp_set_long_long,
+            &mut vec![num, value],
+        )
+    }
+
+    fn emit_get_u64(&self, llvm: &mut LLVM, num: LLVMValueRef) -> LLVMValueRef {
+        llvm.call_func("mp_get_long_long", self.mp_get_long_long, &mut vec![num])
+    }
+
+    fn emit_const(&self, llvm: &mut LLVM, name: &str, value: &BigInt) -> LLVMValueRef {
+        let digit_t = llvm.i64_t();
+        let i32_t = llvm.i32_t();
+        let mut digits: Vec<LLVMValueRef> = value
+            .digits()
+            .iter()
+            .map(|&d| llvm.const_int(digit_t, d as i64))
+            .collect();
+        let used = digits.len() as i64;
+        if digits.is_empty() {
+            digits.push(llvm.const_int(digit_t, 0));
+        }
+        let digits_arr = llvm.const_array(digit_t, &mut digits);
+        let digits_global = llvm.mk_global_const(&format!("{}_digits", name), digits_arr);
+        let sign = if value.is_negative() { MP_NEG } else { MP_ZPOS };
+        let mut fields = [
+            llvm.const_int(i32_t, used),
+            llvm.const_int(i32_t, used),
+            llvm.const_int(i32_t, i64::from(sign)),
+            llvm.const_first_elem_ptr(digits_global),
+        ];
+        let num = llvm.const_named_struct(self.mp_struct, &mut fields);
+        llvm.mk_global_const(name, num)
+    }
+
+    fn emit_parse(
+        &self,
+        llvm: &mut LLVM,
+        num: LLVMValueRef,
+        str_ptr: LLVMValueRef,
+        radix: LLVMValueRef,
+    ) -> LLVMValueRef {
+        llvm.call_func(
+            "mp_read_radix",
+            self.mp_read_radix,
+            &mut vec![num, str_ptr, radix],
+        )
+    }
+
+    fn emit_format_size(
+        &self,
+        llvm: &mut LLVM,
+        num: LLVMValueRef,
+        radix: LLVMValueRef,
+        size_ref: LLVMValueRef,
+    ) -> LLVMValueRef {
+        llvm.call_func(
+            "mp_radix_size",
+            self.mp_radix_size,
+            &mut vec![num, radix, size_ref],
+        )
+    }
+
+    fn emit_format(
+        &self,
+        llvm: &mut LLVM,
+        num: LLVMValueRef,
+        buf: LLVMValueRef,
+        radix: LLVMValueRef,
+    ) -> LLVMValueRef {
+        llvm.call_func("mp_toradix", self.mp_toradix, &mut vec![num, buf, radix])
+    }
+
+    fn emit_binary(
+        &self,
+        llvm: &mut LLVM,
+        op: BignumOp,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+        res: LLVMValueRef,
+    ) -> LLVMValueRef {
+        let null = llvm.const_null(llvm.ptr_t(self.mp_struct));
+        match op {
+            BignumOp::Add => llvm.call_func("mp_add", self.mp_add, &mut vec![lhs, rhs, res]),
+            BignumOp::Sub => llvm.call_func("mp_sub", self.mp_sub, &mut vec![lhs, rhs, res]),
+            BignumOp::Mul => llvm.call_func("mp_mul", self.mp_mul, &mut vec![lhs, rhs, res]),
+            BignumOp::Div => llvm.call_func("mp_div", self.mp_div, &mut vec![lhs, rhs, res, null]),
+            BignumOp::Rem => llvm.call_func("mp_div", self.mp_div, &mut vec![lhs, rhs, null, res]),
+        }
+    }
+
+    fn emit_neg(&self, llvm: &mut LLVM, num: LLVMValueRef, res: LLVMValueRef) -> LLVMValueRef {
+        llvm.call_func("mp_neg", self.mp_neg, &mut vec![num, res])
+    }
+
+    fn emit_cmp(&self, llvm: &mut LLVM, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
+        llvm.call_func("mp_cmp", self.mp_cmp, &mut vec![lhs, rhs])
+    }
+}
+
+fn export_mp_struct(llvm: &mut LLVM) -> LLVMTypeRef {
+    let mut fields = [
+        llvm.i32_t(),
+        llvm.i32_t(),
+        llvm.i32_t(),
+        llvm.ptr_t(llvm.i64_t()),
+    ];
+    llvm.mk_named_struct("mp_struct", &mut fields)
+}
+
+fn export_mp_unary(
+    llvm: &mut LLVM,
+    mp_struct: LLVMTypeRef,
+    name: &str,
+    ret: LLVMTypeRef,
+) -> LLVMValueRef {
+    let mp_sp = llvm.ptr_t(mp_struct);
+    let func_type = llvm.mk_func_type(ret, &mut [mp_sp]);
+    llvm.mk_func(name, func_type)
+}
+
+fn export_mp_copy(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mp_s_p = llvm.ptr_t(mp_struct);
+    let ret = llvm.i32_t();
+    let func_type = llvm.mk_func_type(ret, &mut [mp_s_p, mp_s_p]);
+    llvm.mk_func("mp_copy", func_type)
+}
+
+fn export_mp_set_long_long(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mut args = [llvm.ptr_t(mp_struct), llvm.i64_t()];
+    let ret = llvm.i32_t();
+    let func_type = llvm.mk_func_type(ret, &mut args);
+    llvm.mk_func("mp_set_long_long", func_type)
+}
+
+fn export_mp_binary(llvm: &mut LLVM, mp_struct: LLVMTypeRef, name: &str) -> LLVMValueRef {
+    let mp_s_p = llvm.ptr_t(mp_struct);
+    let mut args = [mp_s_p, mp_s_p, mp_s_p];
+    let ret = llvm.i32_t();
+    let func_type = llvm.mk_func_type(ret, &mut args);
+    llvm.mk_func(name, func_type)
+}
+
+fn export_mp_div(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mp_s_p = llvm.ptr_t(mp_struct);
+    let mut args = [mp_s_p, mp_s_p, mp_s_p, mp_s_p];
+    let ret = llvm.i32_t();
+    let func_type = llvm.mk_func_type(ret, &mut args);
+    llvm.mk_func("mp_div", func_type)
+}
+
+fn export_mp_neg(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mp_s_p = llvm.ptr_t(mp_struct);
+    let ret = llvm.i32_t();
+    let func_type = llvm.mk_func_type(ret, &mut [mp_s_p, mp_s_p]);
+    llvm.mk_func("mp_neg", func_type)
+}
+
+fn export_mp_cmp(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mp_s_p = llvm.ptr_t(mp_struct);
+    let ret = llvm.i32_t();
+    let func_type = llvm.mk_func_type(ret, &mut [mp_s_p, mp_s_p]);
+    llvm.mk_func("mp_cmp", func_type)
+}
+
+fn export_mp_to_radix(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mut args = [llvm.ptr_t(mp_struct), llvm.ptr_t(llvm.i8_t()), llvm.i32_t()];
+    let ret = llvm.i32_t();
+    let mp_toradix_type = llvm.mk_func_type(ret, &mut args);
+    llvm.mk_func("mp_toradix", mp_toradix_type)
+}
+
+fn export_mp_radix_size(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mut args = [
+        llvm.ptr_t(mp_struct),
+        llvm.i32_t(),
+        llvm.ptr_t(llvm.i32_t()),
+    ];
+    let ret = llvm.i32_t();
+    let mp_radix_size = llvm.mk_func_type(ret, &mut args);
+    llvm.mk_func("mp_radix_size", mp_radix_size)
+}
+
+fn export_mp_read_radix(llvm: &mut LLVM, mp_struct: LLVMTypeRef) -> LLVMValueRef {
+    let mut args = [llvm.ptr_t(mp_struct), llvm.ptr_t(llvm.i8_t()), llvm.i32_t()];
+    let ret = llvm.i32_t();
+    let mp_read_radix = llvm.mk_func_type(ret, &mut args);
+    llvm.mk_func("mp_read_radix", mp_read_radix)
+}
@@ -1,45 +1,581 @@
+extern crate llvm_sys;
+
+use self::llvm_sys::support::LLVMAddSymbol;
+use std::cmp::Ordering;
+use std::error::Error;
 use std::ffi::*;
+use std::fmt;
+use std::mem;
+use std::ops::*;
+use std::os::raw::{c_char, c_void};
 use std::ptr::null_mut;
+use std::slice;
+use std::str::FromStr;
+
+/// Digit type used by libtommath when built for a 64 bit target (`MP_64BIT`).
+pub type MpDigit = u64;
+
+/// Number of bits actually used in every `MpDigit`.
+pub const MP_DIGIT_BIT: u32 = 60;
+
+pub const MP_OKAY: i32 = 0;
+pub const MP_ERR: i32 = -1;
+pub const MP_MEM: i32 = -2;
+pub const MP_VAL: i32 = -3;
+
+pub const MP_LT: i32 = -1;
+pub const MP_EQ: i32 = 0;
+pub const MP_GT: i32 = 1;
+
+pub const MP_ZPOS: i32 = 0;
+pub const MP_NEG: i32 = 1;
+
+pub const MIN_RADIX: u32 = 2;
+pub const MAX_RADIX: u32 = 64;
 
 extern "C" {
     fn mp_init(mp: *mut MpInt) -> i32;
-    fn mp_read_radix(mp: *mut MpInt, s: *const i8, radix: i32) -> i32;
-    fn mp_add(mp: *mut MpInt, mp2: *mut MpInt, mp3: *mut MpInt) -> i32;
-    fn mp_radix_size(mp: *mut MpInt, radix: i32, size: *mut i32) -> i32;
-    fn mp_toradix(mp: *mut MpInt, s: *mut i8, radix: i32) -> i32;
+    fn mp_init_copy(mp: *mut MpInt, from: *const MpInt) -> i32;
+    fn mp_clear(mp: *mut MpInt);
+    fn mp_copy(from: *const MpInt, to: *mut MpInt) -> i32;
+    fn mp_set_long_long(mp: *mut MpInt, b: u64) -> i32;
+    fn mp_get_long_long(mp: *const MpInt) -> u64;
+    fn mp_read_radix(mp: *mut MpInt, s: *const c_char, radix: i32) -> i32;
+    fn mp_radix_size(mp: *const MpInt, radix: i32, size: *mut i32) -> i32;
+    fn mp_toradix(mp: *const MpInt, s: *mut c_char, radix: i32) -> i32;
+    fn mp_add(a: *const MpInt, b: *const MpInt, c: *mut MpInt) -> i32;
+    fn mp_sub(a: *const MpInt, b: *const MpInt, c: *mut MpInt) -> i32;
+    fn mp_mul(a: *const MpInt, b: *const MpInt, c: *mut MpInt) -> i32;
+    fn mp_div(a: *const MpInt, b: *const MpInt, c: *mut MpInt, d: *mut MpInt) -> i32;
+    fn mp_neg(a: *const MpInt, b: *mut MpInt) -> i32;
+    fn mp_cmp(a: *const MpInt, b: *const MpInt) -> i32;
 }
 
+/// Mirror of libtommath's `mp_int`.
 #[repr(C)]
-struct MpInt {
-    used: i32,
-    alloc: i32,
-    sign: i32,
-    mp_digit: *mut MpInt,
+pub struct MpInt {
+    pub used: i32,
+    pub alloc: i32,
+    pub sign: i32,
+    pub dp: *mut MpDigit,
 }
 
-pub fn load_bignum_symbols() {
-    unsafe {
-        let mut mp1 = MpInt {
-            used: 1,
-            alloc: 1,
-            sign: 1,
-            mp_digit: null_mut(),
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BigIntError {
+    OutOfMemory,
+    InvalidValue,
+    Failed,
+    Unknown(i32),
+}
+
+impl BigIntError {
+    pub fn from_code(code: i32) -> Result<(), BigIntError> {
+        match code {
+            MP_OKAY => Ok(()),
+            MP_ERR => Err(BigIntError::Failed),
+            MP_MEM => Err(BigIntError::OutOfMemory),
+            MP_VAL => Err(BigIntError::InvalidValue),
+            c => Err(BigIntError::Unknown(c)),
+        }
+    }
+}
+
+impl fmt::Display for BigIntError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BigIntError::OutOfMemory => write!(f, "out of memory"),
+            BigIntError::InvalidValue => write!(f, "invalid value"),
+            BigIntError::Failed => write!(f, "operation failed"),
+            BigIntError::Unknown(code) => write!(f, "unknown libtommath error code {}", code),
+        }
+    }
+}
+
+impl Error for BigIntError {}
+
+/// Arbitrary precision integer backed by libtommath, the same library
+/// the generated code is linked with.
+pub struct BigInt {
+    raw: MpInt,
+}
+
+impl BigInt {
+    pub fn new() -> Result<BigInt, BigIntError> {
+        let mut raw = MpInt {
+            used: 0,
+            alloc: 0,
+            sign: MP_ZPOS,
+            dp: null_mut(),
         };
-        let ptr1 = &mut mp1 as *mut MpInt;
-        mp_init(ptr1);
-        let cstring = CString::new("1").unwrap();
-        let str_ptr = cstring.as_ptr() as *mut _;
-        mp_read_radix(ptr1, str_ptr, 10);
-        mp_add(ptr1, ptr1, ptr1);
-        let mut size: i32 = 100;
-        let ptr_size = &mut size as *mut i32;
-        mp_radix_size(ptr1, 10, ptr_size);
-        let mut s: Vec<i8> = vec![];
-        s.reserve_exact(*ptr_size as usize);
-        s.set_len(*ptr_size as usize);
-        mp_toradix(ptr1, s.as_mut_ptr(), 10);
-        if s[1] == 0 {
-            println!("bigint library has been loaded");
+        BigIntError::from_code(unsafe { mp_init(&mut raw) })?;
+        Ok(BigInt { raw })
+    }
+
+    pub fn from_i64(value: i64) -> Result<BigInt, BigIntError> {
+        let mut num = BigInt::new()?;
+        let magnitude = value.wrapping_abs() as u64;
+        BigIntError::from_code(unsafe { mp_set_long_long(num.as_mut_ptr(), magnitude) })?;
+        if value < 0 {
+            num.checked_neg()
+        } else {
+            Ok(num)
+        }
+    }
+
+    /// The value as `i64` if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        let digits = self.digits();
+        if digits.len() > 2 || (digits.len() == 2 && digits[1] >> (64 - MP_DIGIT_BIT) != 0) {
+            return None;
+        }
+        let magnitude = unsafe { mp_get_long_long(self.as_ptr()) };
+        match (self.is_negative(), magnitude) {
+            (false, m) if m <= i64::MAX as u64 => Some(m as i64),
+            (true, m) if m <= 1 << 63 => Some((m as i64).wrapping_neg()),
+            _ => None,
+        }
+    }
+
+    pub fn from_str_radix(s: &str, radix: u32) -> Result<BigInt, BigIntError> {
+        check_radix(radix)?;
+        let digits = s.strip_prefix('-').unwrap_or(s);
+        if digits.is_empty() {
+            return Err(BigIntError::InvalidValue);
+        }
+        let cstring = CString::new(s).map_err(|_| BigIntError::InvalidValue)?;
+        let mut num = BigInt::new()?;
+        BigIntError::from_code(unsafe {
+            mp_read_radix(num.as_mut_ptr(), cstring.as_ptr(), radix as i32)
+        })?;
+        Ok(num)
+    }
+
+    /// Reads `0x`, `0o` and `0b` prefixed numbers in their radix and any
+    /// other in `default_radix`. The sign goes before the prefix.
+    pub fn from_str_prefixed(s: &str, default_radix: u32) -> Result<BigInt, BigIntError> {
+        let (neg, unsigned) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (radix, digits) = match split_radix_prefix(unsigned) {
+            Some((radix, digits)) => (radix, digits),
+            None => (default_radix, unsigned),
+        };
+        if digits.starts_with('-') {
+            return Err(BigIntError::InvalidValue);
+        }
+        let num = BigInt::from_str_radix(digits, radix)?;
+        if neg {
+            num.checked_neg()
+        } else {
+            Ok(num)
+        }
+    }
+
+    pub fn to_str_radix(&self, radix: u32) -> Result<String, BigIntError> {
+        check_radix(radix)?;
+        let mut size: i32 = 0;
+        BigIntError::from_code(unsafe { mp_radix_size(self.as_ptr(), radix as i32, &mut size) })?;
+        let mut buf: Vec<u8> = vec![0; size as usize];
+        BigIntError::from_code(unsafe {
+            mp_toradix(self.as_ptr(), buf.as_mut_ptr() as *mut c_char, radix as i32)
+        })?;
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+        buf.truncate(len);
+        String::from_utf8(buf).map_err(|_| BigIntError::InvalidValue)
+    }
+
+    pub fn checked_add(&self, other: &BigInt) -> Result<BigInt, BigIntError> {
+        self.binary_op(other, mp_add)
+    }
+
+    pub fn checked_sub(&self, other: &BigInt) -> Result<BigInt, BigIntError> {
+        self.binary_op(other, mp_sub)
+    }
+
+    pub fn checked_mul(&self, other: &BigInt) -> Result<BigInt, BigIntError> {
+        self.binary_op(other, mp_mul)
+    }
+
+    /// Quotient rounded towards zero; division by zero is `InvalidValue`.
+    pub fn checked_div(&self, other: &BigInt) -> Result<BigInt, BigIntError> {
+        let mut res = BigInt::new()?;
+        BigIntError::from_code(unsafe {
+            mp_div(self.as_ptr(), other.as_ptr(), res.as_mut_ptr(), null_mut())
+        })?;
+        Ok(res)
+    }
+
+    /// Remainder with the sign of the dividend, matching `checked_div`.
+    pub fn checked_rem(&self, other: &BigInt) -> Result<BigInt, BigIntError> {
+        let mut res = BigInt::new()?;
+        BigIntError::from_code(unsafe {
+            mp_div(self.as_ptr(), other.as_ptr(), null_mut(), res.as_mut_ptr())
+        })?;
+        Ok(res)
+    }
+
+    pub fn checked_neg(&self) -> Result<BigInt, BigIntError> {
+        let mut res = BigInt::new()?;
+        BigIntError::from_code(unsafe { mp_neg(self.as_ptr(), res.as_mut_ptr()) })?;
+        Ok(res)
+    }
+
+    pub fn try_clone(&self) -> Result<BigInt, BigIntError> {
+        let mut raw: MpInt = unsafe { mem::zeroed() };
+        BigIntError::from_code(unsafe { mp_init_copy(&mut raw, self.as_ptr()) })?;
+        Ok(BigInt { raw })
+    }
+
+    /// `self` raised to `exp` by repeated squaring.
+    pub fn pow(&self, mut exp: u32) -> Result<BigInt, BigIntError> {
+        let mut res = BigInt::from_str_radix("1", 10)?;
+        let mut base = self.try_clone()?;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                res = res.checked_mul(&base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.checked_mul(&base)?;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Magnitude as libtommath digits of `MP_DIGIT_BIT` bits, least significant first.
+    pub fn digits(&self) -> &[MpDigit] {
+        if self.raw.used == 0 {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.raw.dp, self.raw.used as usize) }
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.raw.sign == MP_NEG
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.raw.used == 0
+    }
+
+    pub fn as_ptr(&self) -> *const MpInt {
+        &self.raw
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut MpInt {
+        &mut self.raw
+    }
+
+    fn binary_op(
+        &self,
+        other: &BigInt,
+        op: unsafe extern "C" fn(*const MpInt, *const MpInt, *mut MpInt) -> i32,
+    ) -> Result<BigInt, BigIntError> {
+        let mut res = BigInt::new()?;
+        BigIntError::from_code(unsafe { op(self.as_ptr(), other.as_ptr(), res.as_mut_ptr()) })?;
+        Ok(res)
+    }
+}
+
+/// Radix selected by a `0x`, `0o` or `0b` prefix and the digits after it.
+pub fn split_radix_prefix(s: &str) -> Option<(u32, &str)> {
+    let bytes = s.as_bytes();
+    if bytes.len() < 2 || bytes[0] != b'0' {
+        return None;
+    }
+    match bytes[1] | 0x20 {
+        b'x' => Some((16, &s[2..])),
+        b'o' => Some((8, &s[2..])),
+        b'b' => Some((2, &s[2..])),
+        _ => None,
+    }
+}
+
+fn check_radix(radix: u32) -> Result<(), BigIntError> {
+    if (MIN_RADIX..=MAX_RADIX).contains(&radix) {
+        Ok(())
+    } else {
+        Err(BigIntError::InvalidValue)
+    }
+}
+
+impl Drop for BigInt {
+    fn drop(&mut self) {
+        unsafe { mp_clear(&mut self.raw) }
+    }
+}
+
+impl Clone for BigInt {
+    fn clone(&self) -> Self {
+        self.try_clone()
+            .unwrap_or_else(|e| panic!("can't clone bignum: {}", e))
+    }
+}
+
+impl FromStr for BigInt {
+    type Err = BigIntError;
+
+    fn from_str(s: &str) -> Result<BigInt, BigIntError> {
+        BigInt::from_str_radix(s, 10)
+    }
+}
+
+fn fmt_radix(num: &BigInt, f: &mut fmt::Formatter, radix: u32, lower: bool) -> fmt::Result {
+    let s = num.to_str_radix(radix).map_err(|_| fmt::Error)?;
+    let s = if lower { s.to_lowercase() } else { s };
+    let (non_negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (false, digits),
+        None => (true, &s[..]),
+    };
+    let prefix = match radix {
+        16 => "0x",
+        8 => "0o",
+        2 => "0b",
+        _ => "",
+    };
+    f.pad_integral(non_negative, prefix, digits)
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_radix(self, f, 10, false)
+    }
+}
+
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_radix(self, f, 10, false)
+    }
+}
+
+impl fmt::LowerHex for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_radix(self, f, 16, true)
+    }
+}
+
+impl fmt::UpperHex for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_radix(self, f, 16, false)
+    }
+}
+
+impl fmt::Octal for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_radix(self, f, 8, false)
+    }
+}
+
+impl fmt::Binary for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_radix(self, f, 2, false)
+    }
+}
+
+impl PartialEq for BigInt {
+    fn eq(&self, other: &BigInt) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for BigInt {}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match unsafe { mp_cmp(self.as_ptr(), other.as_ptr()) } {
+            MP_LT => Ordering::Less,
+            MP_EQ => Ordering::Equal,
+            _ => Ordering::Greater,
+        }
+    }
+}
+
+macro_rules! impl_bin_op {
+    ($tr:ident, $method:ident, $checked:ident) => {
+        impl<'a, 'b> $tr<&'b BigInt> for &'a BigInt {
+            type Output = BigInt;
+
+            fn $method(self, other: &'b BigInt) -> BigInt {
+                self.$checked(other)
+                    .unwrap_or_else(|e| panic!("bignum {} failed: {}", stringify!($method), e))
+            }
+        }
+
+        impl<'a> $tr<&'a BigInt> for BigInt {
+            type Output = BigInt;
+
+            fn $method(self, other: &'a BigInt) -> BigInt {
+                (&self).$method(other)
+            }
+        }
+
+        impl<'a> $tr<BigInt> for &'a BigInt {
+            type Output = BigInt;
+
+            fn $method(self, other: BigInt) -> BigInt {
+                self.$method(&other)
+            }
+        }
+
+        impl $tr<BigInt> for BigInt {
+            type Output = BigInt;
+
+            fn $method(self, other: BigInt) -> BigInt {
+                (&self).$method(&other)
+            }
+        }
+    };
+}
+
+impl_bin_op!(Add, add, checked_add);
+impl_bin_op!(Sub, sub, checked_sub);
+impl_bin_op!(Mul, mul, checked_mul);
+impl_bin_op!(Div, div, checked_div);
+impl_bin_op!(Rem, rem, checked_rem);
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        self.checked_neg()
+            .unwrap_or_else(|e| panic!("bignum neg failed: {}", e))
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        -&self
+    }
+}
+
+/// Makes the bignum functions linked into this binary visible to the JIT,
+/// which otherwise only resolves symbols exported by shared libraries.
+fn register_bignum_symbols() {
+    let symbols: [(&str, *mut c_void); 15] = [
+        ("mp_init", mp_init as *mut c_void),
+        ("mp_init_copy", mp_init_copy as *mut c_void),
+        ("mp_clear", mp_clear as *mut c_void),
+        ("mp_copy", mp_copy as *mut c_void),
+        ("mp_set_long_long", mp_set_long_long as *mut c_void),
+        ("mp_get_long_long", mp_get_long_long as *mut c_void),
+        ("mp_read_radix", mp_read_radix as *mut c_void),
+        ("mp_radix_size", mp_radix_size as *mut c_void),
+        ("mp_toradix", mp_toradix as *mut c_void),
+        ("mp_add", mp_add as *mut c_void),
+        ("mp_sub", mp_sub as *mut c_void),
+        ("mp_mul", mp_mul as *mut c_void),
+        ("mp_div", mp_div as *mut c_void),
+        ("mp_neg", mp_neg as *mut c_void),
+        ("mp_cmp", mp_cmp as *mut c_void),
+    ];
+    for &(name, addr) in symbols.iter() {
+        let name = CString::new(name).unwrap();
+        unsafe { LLVMAddSymbol(name.as_ptr(), addr) }
+    }
+}
+
+pub fn load_bignum_symbols() {
+    register_bignum_symbols();
+    let loaded = "1"
+        .parse::<BigInt>()
+        .and_then(|one| one.checked_add(&one))
+        .and_then(|two| two.to_str_radix(10));
+    match loaded {
+        Ok(ref s) if s == "2" => debug!("bigint library has been loaded"),
+        Ok(s) => error!("bigint library returned unexpected result: {}", s),
+        Err(e) => error!("bigint library can't be loaded: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_formats() {
+        let n = "-123456789012345678901234567890";
+        assert_eq!(big(n).to_string(), n);
+        assert_eq!(big("-0").to_string(), "0");
+        assert!(!big("-0").is_negative());
+        assert_eq!(format!("{:x}", big("-255")), "-ff");
+        assert_eq!(format!("{:X}", big("255")), "FF");
+        assert_eq!(format!("{:b}", big("5")), "101");
+        let hex = "123456789ABCDEF0123456789ABCDEF";
+        assert_eq!(
+            BigInt::from_str_radix(hex, 16)
+                .unwrap()
+                .to_str_radix(16)
+                .unwrap(),
+            hex
+        );
+        assert_eq!(BigInt::from_str_prefixed("-0x1f", 10).unwrap(), big("-31"));
+        assert_eq!(BigInt::from_str_prefixed("0b101", 10).unwrap(), big("5"));
+        assert_eq!(BigInt::from_str_prefixed("17", 8).unwrap(), big("15"));
+        for s in ["", "-", "--5", "-0x-5", "0x"].iter() {
+            assert!(BigInt::from_str_prefixed(s, 10).is_err(), "{:?}", s);
+        }
+        assert_eq!(big("-9223372036854775808").to_i64(), Some(i64::MIN));
+        assert_eq!(big("9223372036854775807").to_i64(), Some(i64::MAX));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(BigInt::from_i64(i64::MIN).unwrap().to_i64(), Some(i64::MIN));
+    }
+
+    #[test]
+    fn checked_ops() {
+        let (a, b) = (big("-7"), big("2"));
+        assert_eq!(&a + &b, big("-5"));
+        assert_eq!(&a - &b, big("-9"));
+        assert_eq!(&a * &b, big("-14"));
+        assert_eq!(&a / &b, big("-3"));
+        assert_eq!(&a % &b, big("-1"));
+        assert_eq!(-&a, big("7"));
+        assert!(a < b && -&a > b);
+        let zero = BigInt::new().unwrap();
+        assert_eq!(a.checked_div(&zero), Err(BigIntError::InvalidValue));
+        assert_eq!(a.checked_rem(&zero), Err(BigIntError::InvalidValue));
+    }
+
+    #[test]
+    fn powers_and_digits() {
+        let two = big("2");
+        let mersenne = &two.pow(521).unwrap() - &big("1");
+        assert_eq!(mersenne.to_str_radix(16).unwrap().len(), 131);
+        assert!(mersenne.to_str_radix(2).unwrap().chars().all(|c| c == '1'));
+        assert_eq!(big("-7").pow(0).unwrap(), big("1"));
+        assert_eq!(big("-3").pow(3).unwrap(), big("-27"));
+
+        assert!(big("0").digits().is_empty());
+        let top = &two.pow(MP_DIGIT_BIT).unwrap() + &big("5");
+        assert_eq!(top.digits(), &[5, 1]);
+        assert_eq!((-&top).digits(), top.digits());
+    }
+
+    #[test]
+    fn radix_bounds() {
+        for &radix in [MIN_RADIX, 10, MAX_RADIX].iter() {
+            let s = big("-1000").to_str_radix(radix).unwrap();
+            assert_eq!(BigInt::from_str_radix(&s, radix).unwrap(), big("-1000"));
+        }
+        for &radix in [0, 1, MAX_RADIX + 1].iter() {
+            assert_eq!(
+                BigInt::from_str_radix("1", radix),
+                Err(BigIntError::InvalidValue)
+            );
+            assert_eq!(big("1").to_str_radix(radix), Err(BigIntError::InvalidValue));
         }
     }
 }
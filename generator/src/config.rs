@@ -0,0 +1,104 @@
+//! Project file, `Calc.toml` by default, giving the defaults of the options
+//! which the environment and the command line override.
+//!
+//! ```toml
+//! [llvm]
+//! prefix = "/usr/local/opt/llvm"
+//!
+//! [build]
+//! target = "x86_64-apple-darwin"
+//! opt-level = 2
+//! out-dir = "target"
+//! libs = ["m"]
+//! lib-paths = ["../test-lib/target/debug"]
+//! ```
+
+extern crate toml;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_PATH: &str = "Calc.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub llvm: LlvmConfig,
+    pub build: BuildConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LlvmConfig {
+    /// Installation of LLVM whose `clang` links executables.
+    pub prefix: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct BuildConfig {
+    pub target: Option<String>,
+    pub opt_level: Option<u32>,
+    pub out_dir: Option<PathBuf>,
+    pub libs: Vec<String>,
+    pub lib_paths: Vec<PathBuf>,
+}
+
+impl Config {
+    /// Reads the project file `path`. Relative paths in it are taken from
+    /// the directory of the file.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let mut config: Config =
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if let Some(opt_level) = config.build.opt_level {
+            if opt_level > 3 {
+                return Err(format!(
+                    "{}: opt-level must be between 0 and 3, got {}",
+                    path.display(),
+                    opt_level
+                ));
+            }
+        }
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        config.llvm.prefix = config.llvm.prefix.map(|p| dir.join(p));
+        config.build.out_dir = config.build.out_dir.map(|p| dir.join(p));
+        for lib_path in &mut config.build.lib_paths {
+            *lib_path = dir.join(&*lib_path);
+        }
+        Ok(config)
+    }
+
+    /// Reads `path`, or `Calc.toml` when it exists if no path is given.
+    pub fn find(path: Option<&Path>) -> Result<Config, String> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_PATH).exists() => Config::load(Path::new(DEFAULT_PATH)),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_project_files() {
+        let config: Config = toml::from_str(
+            "[llvm]\nprefix = \"/opt/llvm\"\n\n[build]\nopt-level = 2\nlibs = [\"m\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.llvm.prefix, Some(PathBuf::from("/opt/llvm")));
+        assert_eq!(config.build.opt_level, Some(2));
+        assert_eq!(config.build.libs, vec!["m".to_owned()]);
+        assert_eq!(config.build.target, None);
+        assert!(config.build.lib_paths.is_empty());
+
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.build.out_dir, None);
+
+        assert!(toml::from_str::<Config>("[build]\nopt_level = 2\n").is_err());
+    }
+}
@@ -2,19 +2,50 @@ extern crate libc;
 extern crate llvm_sys;
 
 use self::llvm_sys::prelude::*;
+use self::llvm_sys::LLVMIntPredicate;
+use self::llvm_sys::LLVMOpcode;
+use ast;
+use bignum::*;
 use bignumloader::*;
+use input;
 use llvm::*;
+use mir;
+use report::{self, Report};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::Instant;
 
-pub fn llvm_exec() -> bool {
+mod lower;
+
+/// Makes the runtimes linked into the generator available to the JIT.
+fn load_runtime_symbols() {
     load_bignum_symbols();
-    let mut runner = LLVMRunner::new();
+    input::register_symbols();
+}
+
+/// Runs the built in demo with the JIT, giving it the command line `args`,
+/// and returns its exit status; `None` when it can't be run.
+pub fn llvm_exec(options: &Options, args: &[String]) -> Option<i32> {
+    load_runtime_symbols();
+    let mut runner = LLVMRunner::with_options(ErrorMode::Checked, options);
     let main = mk_main(&mut runner);
-    runner.llvm.dump("output");
-    runner.llvm.exec_func(main)
+    runner.llvm.optimize(options.opt_level);
+    if options.dump {
+        dump_ir(&runner.llvm, "output");
+    }
+    runner.llvm.run_main(main, args)
 }
 
-pub fn llvm_compile(out_name: &str) -> bool {
-    let mut runner = LLVMRunner::new();
+/// Builds the demo calling into `test-lib` into the executable `out_path`,
+/// the library has to be linked through `options.libs` and `lib_paths`.
+pub fn llvm_compile(out_name: &str, out_path: &str, options: &Options) -> bool {
+    let mut report = Report::start(options.opt_level);
+    let mut runner = LLVMRunner::with_options(ErrorMode::Unchecked, options);
     runner.mk_main_func(|ref mut r| {
         r.call_hello_world_function();
         r.call_printf_func("Hello, .\n", "");
@@ -24,12 +55,8 @@ pub fn llvm_compile(out_name: &str) -> bool {
         r.call_slice();
         r.call_hello_one("Bob");
     });
-    runner.llvm.dump(out_name);
-    if runner.llvm.mk_object_file(out_name) {
-        link()
-    } else {
-        false
-    }
+    let ok = mk_executable(&mut runner.llvm, out_name, out_path, options, &mut report);
+    finish_report(report, ok, options)
 }
 
 fn mk_main(runner: &mut LLVMRunner) -> LLVMValueRef {
@@ -37,76 +64,1058 @@ fn mk_main(runner: &mut LLVMRunner) -> LLVMValueRef {
         r.call_printf_func_one("Hello from JIT generated executable!\n");
         let i8_pt = r.llvm.ptr_t(r.llvm.i8_t());
         let i32_t = r.llvm.i32_t();
-        let num_ref1 = r.llvm.build_alloca("num1", r.structs.mp_struct);
-        let num_ref2 = r.llvm.build_alloca("num2", r.structs.mp_struct);
-        let res_str_ptr = r.llvm.build_alloca("res_str", i8_pt);
-        let res_num_ref = r.llvm.build_alloca("res_num", r.structs.mp_struct);
+        let num_ref1 = r.alloc_hybrid("num1");
+        let num_ref2 = r.alloc_hybrid("num2");
+        let res_str_ptr = r.alloc_tracked("res_str", i8_pt, Destructor::Free);
+        let res_hybrid_ref = r.alloc_hybrid("res_num");
         let str_size_ref = r.llvm.build_alloca("str_size", i32_t);
-        let array_i8t = r.llvm.arr_t(r.llvm.i8_t(), 100);
-        let input_str_ref = r.llvm.build_alloca("num1_input", array_i8t);
-        r.call_mp_init(num_ref1);
-        r.call_mp_init(num_ref2);
-        r.call_mp_init(res_num_ref);
-        r.call_scanf_func("%s", input_str_ref);
-        r.call_mp_read_radix(num_ref1, input_str_ref);
-        r.call_scanf_func("%s", input_str_ref);
-        r.call_mp_read_radix(num_ref2, input_str_ref);
-        r.call_mp_add(num_ref1, num_ref2, res_num_ref);
-        r.call_radix_size(res_num_ref, str_size_ref);
+        r.read_operands(&[num_ref1, num_ref2]);
+        let radix = r.radix;
+        r.call_hybrid_op(BignumOp::Add, num_ref1, num_ref2, res_hybrid_ref);
+        let res_num_ref = r.hybrid_to_bignum(res_hybrid_ref);
+        r.call_bignum_format_size(res_num_ref, str_size_ref, radix.output);
         let str_ref = r.call_malloc(str_size_ref);
-        r.call_mp_toradix(str_ref, res_num_ref, res_str_ptr);
+        r.call_bignum_format(str_ref, res_num_ref, res_str_ptr, radix.output);
         let loaded = r.llvm.build_load(res_str_ptr);
         r.call_printf_func_by_value("Result of adding two inputted numbers: %s\n", loaded);
         r.call_printf_func_one("Goodbye from JIT generated executable\n");
     })
 }
 
-pub fn llvm_compile2(out_name: &str) -> bool {
-    let mut runner = LLVMRunner::new();
-    mk_main(&mut runner);
-    runner.llvm.dump(out_name);
-    if runner.llvm.mk_object_file(out_name) {
-        link()
+pub fn llvm_compile2(out_name: &str, out_path: &str, options: &Options) -> bool {
+    let mut report = Report::start(options.opt_level);
+    let mut runner = report::time(&mut report.timings.ir_build, || {
+        let mut runner = LLVMRunner::with_options(ErrorMode::Checked, options);
+        mk_main(&mut runner);
+        runner
+    });
+    let ok = mk_executable(&mut runner.llvm, out_name, out_path, options, &mut report);
+    finish_report(report, ok, options)
+}
+
+/// Verifies and optimizes the module and links it into the executable
+/// `out_path`, the intermediate files being named after `out_name`. What
+/// the phases take and produce goes to `report`.
+fn mk_executable(
+    llvm: &mut LLVM,
+    out_name: &str,
+    out_path: &str,
+    options: &Options,
+    report: &mut Report,
+) -> bool {
+    if let Err(e) = report::time(&mut report.timings.verify, || llvm.verify()) {
+        error!("invalid module: {}", e);
+        return false;
+    }
+    report::time(&mut report.timings.optimize, || {
+        llvm.optimize(options.opt_level)
+    });
+    let (functions, instructions) = llvm.code_size();
+    report.functions = functions;
+    report.instructions = instructions;
+    dump_ir(llvm, out_name);
+    let target = options.target.as_deref();
+    report.target = Some(llvm.target_info(target));
+    let object = llvm.out_file(&format!("{}.o", out_name));
+    if !report::time(&mut report.timings.codegen, || {
+        llvm.mk_object_file(out_name, target, options.opt_level)
+    }) {
+        return false;
+    }
+    report.object_size = file_size(&object);
+    let linker = Linker {
+        llvm_prefix: options.llvm_prefix.as_deref(),
+        triple: target,
+        libs: &options.libs,
+        lib_paths: &options.lib_paths,
+    };
+    if !report::time(&mut report.timings.link, || {
+        link(&object, out_path, &linker)
+    }) {
+        return false;
+    }
+    report.executable_size = file_size(out_path);
+    true
+}
+
+fn file_size(path: &str) -> Option<u64> {
+    fs::metadata(path).map(|metadata| metadata.len()).ok()
+}
+
+/// Ends `report` of a build which succeeded or not and writes it to the
+/// path given in the options, if any.
+fn finish_report(mut report: Report, success: bool, options: &Options) -> bool {
+    report.finish(success);
+    let path = match options.report {
+        Some(ref path) => path,
+        None => return success,
+    };
+    match report.write(path) {
+        Ok(()) => {
+            info!("Writing the build report: {}", path.display());
+            success
+        }
+        Err(e) => {
+            error!("can't write the build report {}: {}", path.display(), e);
+            false
+        }
+    }
+}
+
+/// Parses `source` as a calculator program and runs it with the JIT like
+/// `llvm_exec`.
+pub fn llvm_exec_program(source: &str, options: &Options, args: &[String]) -> Option<i32> {
+    let program = mk_mir(source, "output", options.dump_dir())?;
+    load_runtime_symbols();
+    let mut runner = LLVMRunner::with_options(ErrorMode::Checked, options);
+    let main = runner.lower_program(&program);
+    runner.llvm.optimize(options.opt_level);
+    if options.dump {
+        dump_ir(&runner.llvm, "output");
+    }
+    runner.llvm.run_main(main, args)
+}
+
+/// Parses `source` as a calculator program and builds the executable
+/// `out_path` of it. The files generated on the way are named after
+/// `out_name`.
+pub fn llvm_compile_program(
+    source: &str,
+    out_name: &str,
+    out_path: &str,
+    options: &Options,
+) -> bool {
+    let mut report = Report::start(options.opt_level);
+    let runner = report::time(&mut report.timings.ir_build, || {
+        let program = mk_mir(source, out_name, Some(&options.out_dir))?;
+        let mut runner = LLVMRunner::with_options(ErrorMode::Checked, options);
+        runner.lower_program(&program);
+        Some(runner)
+    });
+    let ok = match runner {
+        Some(mut runner) => {
+            mk_executable(&mut runner.llvm, out_name, out_path, options, &mut report)
+        }
+        None => false,
+    };
+    finish_report(report, ok, options)
+}
+
+/// Parses `source` as a calculator program and writes the `emit` kinds of
+/// output for it to `<out_dir>/<out_name>.*`.
+pub fn llvm_emit_program(source: &str, out_name: &str, emit: &[Emit], options: &Options) -> bool {
+    let mir_dir = if emit.contains(&Emit::Mir) {
+        Some(options.out_dir.as_path())
     } else {
-        false
+        None
+    };
+    let program = match mk_mir(source, out_name, mir_dir) {
+        Some(program) => program,
+        None => return false,
+    };
+    let mut runner = LLVMRunner::with_options(ErrorMode::Checked, options);
+    runner.lower_program(&program);
+    runner.llvm.optimize(options.opt_level);
+    let target = options.target.as_deref();
+    let mut ok = true;
+    for kind in emit {
+        ok &= match *kind {
+            // written by `mk_mir`
+            Emit::Mir => true,
+            Emit::LlvmIr => {
+                dump_ir(&runner.llvm, out_name);
+                true
+            }
+            Emit::Asm => runner.llvm.mk_asm_file(out_name, target, options.opt_level),
+            Emit::Obj => runner
+                .llvm
+                .mk_object_file(out_name, target, options.opt_level),
+        };
+    }
+    ok
+}
+
+/// Reads programs from the standard input and runs each one with the JIT as
+/// soon as it is complete. Functions and top level variables stay defined
+/// for the next programs, and a failure only ends the program it occurs in.
+/// The programs see the command line `args`, its name first.
+pub fn llvm_repl(options: &Options, args: &[String]) -> bool {
+    let mut repl = Repl::new(options, args);
+    let mut source = String::new();
+    loop {
+        print!("{}", if source.is_empty() { "> " } else { "| " });
+        let _ = io::stdout().flush();
+        // through C's stdin, which `read` in the programs shares
+        match input::read_stdin_line() {
+            Ok(None) => break,
+            Ok(Some(line)) => {
+                source.push_str(&line);
+                source.push('\n');
+            }
+            Err(e) => {
+                error!("can't read the input: {}", e);
+                return false;
+            }
+        }
+        if source.trim().is_empty() {
+            source.clear();
+            continue;
+        }
+        if ast::is_incomplete(&source) {
+            continue;
+        }
+        let ran = repl.run(&source);
+        source.clear();
+        if ran.is_none() {
+            return false;
+        }
+    }
+    println!();
+    true
+}
+
+/// JIT session of the REPL, which the programs it runs add their modules to.
+struct Repl<'o> {
+    llvm: Option<LLVM>,
+    session: mir::Session,
+    /// `FAILED_FLAG`, owned by the host.
+    failed: *mut u8,
+    options: &'o Options,
+}
+
+impl<'o> Repl<'o> {
+    fn new(options: &'o Options, args: &[String]) -> Self {
+        load_runtime_symbols();
+        // outlives the generated code setting it
+        let failed = Box::into_raw(Box::new(0u8));
+        LLVM::add_symbol(FAILED_FLAG, failed as *mut libc::c_void);
+        add_arg_symbols(args);
+        let mut llvm = LLVM::new();
+        llvm.out_dir = options.out_dir.clone();
+        Repl {
+            llvm: Some(llvm),
+            session: mir::Session::new(),
+            failed,
+            options,
+        }
+    }
+
+    /// Runs the complete program `source`, returning whether it ran to its
+    /// end; `None` when the JIT fails, which ends the session. Errors are
+    /// printed.
+    fn run(&mut self, source: &str) -> Option<bool> {
+        let built = ast::parse(source)
+            .and_then(|program| mir::build_in_session(source, &program, &mut self.session));
+        let mut program = match built {
+            Ok(program) => program,
+            Err(e) => {
+                error!("{}", e);
+                return Some(false);
+            }
+        };
+        mir::fold_constants(&mut program);
+
+        let name = program.functions[0].name.clone();
+        let mut llvm = self.llvm.take().unwrap();
+        llvm.new_module(&name);
+        let mut runner = LLVMRunner::with_llvm(llvm, ErrorMode::Recover, self.options.backend);
+        runner.radix = self.options.radix;
+        runner.lower_program(&program);
+        let llvm = self.llvm.get_or_insert(runner.llvm);
+        llvm.optimize(self.options.opt_level);
+        if self.options.dump {
+            dump_ir(llvm, &name);
+        }
+        if !llvm.add_to_jit() {
+            return None;
+        }
+        let addr = match llvm.func_address(&name) {
+            Some(addr) => addr,
+            None => {
+                error!("function {} has not been compiled", name);
+                return None;
+            }
+        };
+        let entry: extern "C" fn() = unsafe { mem::transmute(addr as usize) };
+        unsafe { ptr::write_volatile(self.failed, 0) };
+        entry();
+        // the generated code prints through the C library's buffer
+        unsafe { libc::fflush(ptr::null_mut()) };
+        Some(unsafe { ptr::read_volatile(self.failed) } == 0)
     }
 }
 
+/// Defines the globals `main` would set for code without one, which the
+/// REPL runs.
+fn add_arg_symbols(args: &[String]) {
+    // outlive the generated code reading them
+    let mut argv: Vec<*const libc::c_char> = args
+        .iter()
+        .map(|arg| CString::new(arg.as_str()).unwrap().into_raw() as *const _)
+        .collect();
+    argv.push(std::ptr::null());
+    let argv = Box::leak(Box::new(Box::leak(argv.into_boxed_slice()).as_ptr()));
+    let argc = Box::leak(Box::new(args.len() as i32));
+    LLVM::add_symbol(ARGC_GLOBAL, argc as *mut i32 as *mut libc::c_void);
+    LLVM::add_symbol(ARGV_GLOBAL, argv as *mut _ as *mut libc::c_void);
+}
+
+/// Parses and type checks `source`, then folds its constants. The result
+/// is written to `<out_dir>/<name>.mir` when `out_dir` is given, errors are
+/// printed.
+fn mk_mir(source: &str, name: &str, out_dir: Option<&Path>) -> Option<mir::Program> {
+    match ast::parse(source).and_then(|program| mir::build(source, &program)) {
+        Ok(mut program) => {
+            mir::fold_constants(&mut program);
+            if let Some(out_dir) = out_dir {
+                let file_name = out_dir.join(format!("{}.mir", name));
+                info!("Dumping MIR to the file: {}", file_name.display());
+                let written = fs::create_dir_all(out_dir)
+                    .and_then(|_| fs::write(&file_name, program.to_string()));
+                if let Err(e) = written {
+                    warn!(
+                        "The file '{}' can't be written because of the error: {}",
+                        file_name.display(),
+                        e
+                    );
+                }
+            }
+            Some(program)
+        }
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
+}
+
+/// Writes the IR of the module to `<out_dir>/<name>.ll`, printing where.
+fn dump_ir(llvm: &LLVM, name: &str) {
+    match llvm.dump(name) {
+        Ok(path) => info!("Dumping LLVM IR to the file: {}", path.display()),
+        Err(e) => warn!(
+            "The LLVM IR of {} can't be dumped because of the error: {}",
+            name, e
+        ),
+    }
+}
+
+/// Operands of the benchmark workloads: `(a * b + c - a) / c` is computed
+/// on every iteration.
+const BENCH_WORKLOADS: [(&str, [i64; 3]); 2] = [
+    ("typical", [123_456_789, 987_654_321, 97]),
+    ("overflowing", [1 << 62, 987_654_321, 97]),
+];
+
+/// Time per iteration of a benchmark workload in one representation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchTiming {
+    pub workload: &'static str,
+    /// `"bignum"` or `"hybrid"`.
+    pub repr: &'static str,
+    pub ns_per_iter: f64,
+}
+
+/// Times the same arithmetic done with bignums only and with hybrid integers,
+/// `None` when it can't be run.
+pub fn llvm_bench(iterations: u64, options: &Options) -> Option<Vec<BenchTiming>> {
+    load_runtime_symbols();
+    let mut runner = LLVMRunner::with_options(ErrorMode::Checked, options);
+    for &(name, operands) in BENCH_WORKLOADS.iter() {
+        runner.mk_bench_bignum(&format!("bench_bignum_{}", name), operands);
+        runner.mk_bench_hybrid(&format!("bench_hybrid_{}", name), operands);
+    }
+    if options.dump {
+        dump_ir(&runner.llvm, "bench");
+    }
+    if !runner.llvm.start_jit() {
+        return None;
+    }
+    let mut timings = vec![];
+    for &(name, _) in BENCH_WORKLOADS.iter() {
+        for &repr in ["bignum", "hybrid"].iter() {
+            let func_name = format!("bench_{}_{}", repr, name);
+            let addr = match runner.llvm.func_address(&func_name) {
+                Some(addr) => addr,
+                None => {
+                    error!("function {} has not been compiled", func_name);
+                    return None;
+                }
+            };
+            let bench: extern "C" fn(i64) = unsafe { mem::transmute(addr as usize) };
+            let start = Instant::now();
+            bench(iterations as i64);
+            let elapsed = start.elapsed();
+            timings.push(BenchTiming {
+                workload: name,
+                repr,
+                ns_per_iter: elapsed.as_secs_f64() * 1e9 / iterations.max(1) as f64,
+            });
+        }
+    }
+    Some(timings)
+}
+
+/// Whether generated code checks the status returned by every bignum call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorMode {
+    /// A failure ends the process.
+    Checked,
+    Unchecked,
+    /// A failure releases the resources of the function it happens in and
+    /// returns from it with `FAILED_FLAG` set, and so do its callers.
+    Recover,
+}
+
+/// `i8` global set by the generated code running with `ErrorMode::Recover`
+/// when it fails, and cleared by the host.
+const FAILED_FLAG: &str = "calc.failed";
+
+/// Globals `main` stores its `argc` and `argv` into for the generated code.
+const ARGC_GLOBAL: &str = "calc.argc";
+const ARGV_GLOBAL: &str = "calc.argv";
+
+/// Longest number the generated code reads, in digits; reading a longer one
+/// fails rather than exhausting the memory.
+const MAX_INPUT_LEN: i64 = 1 << 20;
+
+/// How digit strings read by generated code are interpreted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputRadix {
+    /// Digits are always read in the given radix.
+    Fixed(u32),
+    /// `0x`, `0o` and `0b` prefixes select the radix, anything else is decimal.
+    Prefixed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RadixConfig {
+    pub input: InputRadix,
+    pub output: u32,
+}
+
+impl Default for RadixConfig {
+    fn default() -> Self {
+        RadixConfig {
+            input: InputRadix::Prefixed,
+            output: 10,
+        }
+    }
+}
+
+impl RadixConfig {
+    /// Parses a radix given on the command line, `auto` meaning prefixed input.
+    pub fn parse_input(s: &str) -> Result<InputRadix, String> {
+        if s == "auto" {
+            Ok(InputRadix::Prefixed)
+        } else {
+            RadixConfig::parse_output(s).map(InputRadix::Fixed)
+        }
+    }
+
+    pub fn parse_output(s: &str) -> Result<u32, String> {
+        match s.parse::<u32>() {
+            Ok(radix) if (MIN_RADIX..=MAX_RADIX).contains(&radix) => Ok(radix),
+            _ => Err(format!(
+                "radix must be between {} and {}, got '{}'",
+                MIN_RADIX, MAX_RADIX, s
+            )),
+        }
+    }
+}
+
+/// Where the built in demo reads the numbers it adds.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operands {
+    /// Whitespace delimited from the standard input.
+    Stdin,
+    /// The first command line arguments of the program.
+    Args,
+    /// Whitespace delimited from the file, opened when the program runs.
+    File(PathBuf),
+    /// Built into the program as global strings.
+    Given(Vec<String>),
+}
+
+impl Operands {
+    /// Parses `stdin`, `args`, `file:<path>` or the numbers themselves,
+    /// separated by a comma.
+    pub fn parse(s: &str) -> Result<Operands, String> {
+        match s {
+            "stdin" => return Ok(Operands::Stdin),
+            "args" => return Ok(Operands::Args),
+            _ if s.starts_with("file:") => return Ok(Operands::File(PathBuf::from(&s[5..]))),
+            _ => {}
+        }
+        let values: Vec<String> = s.split(',').map(str::to_owned).collect();
+        if values.len() == 2 && values.iter().all(|value| !value.is_empty()) {
+            Ok(Operands::Given(values))
+        } else {
+            Err(format!(
+                "expected stdin, args, file:<path> or two numbers separated by a comma, got '{}'",
+                s
+            ))
+        }
+    }
+}
+
+/// How programs are compiled, linked and run.
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub radix: RadixConfig,
+    /// Bignum library the generated code calls.
+    pub backend: Backend,
+    /// LLVM optimization level, 0 to 3.
+    pub opt_level: u32,
+    /// Target triple of compiled code, the host's when `None`.
+    pub target: Option<String>,
+    /// Libraries linked into executables besides the runtime.
+    pub libs: Vec<String>,
+    /// Directories searched for `libs`.
+    pub lib_paths: Vec<PathBuf>,
+    /// LLVM installation whose `clang` links executables, `cc` otherwise.
+    pub llvm_prefix: Option<PathBuf>,
+    /// Directory of the generated files.
+    pub out_dir: PathBuf,
+    /// Whether programs run with the JIT have their MIR and LLVM IR written
+    /// to `out_dir`, as builds always do.
+    pub dump: bool,
+    /// Where builds of executables write their JSON report, if anywhere.
+    pub report: Option<PathBuf>,
+    /// Where the built in demo reads its numbers.
+    pub operands: Operands,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            radix: RadixConfig::default(),
+            backend: Backend::default(),
+            opt_level: 0,
+            target: None,
+            libs: vec![],
+            lib_paths: vec![],
+            llvm_prefix: None,
+            out_dir: PathBuf::from("./target"),
+            dump: false,
+            report: None,
+            operands: Operands::Stdin,
+        }
+    }
+}
+
+impl Options {
+    /// Where programs run with the JIT are dumped, if anywhere.
+    fn dump_dir(&self) -> Option<&Path> {
+        if self.dump {
+            Some(&self.out_dir)
+        } else {
+            None
+        }
+    }
+}
+
+/// Kind of file written by `llvm_emit_program`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Mir,
+    LlvmIr,
+    Asm,
+    Obj,
+}
+
+impl Emit {
+    pub const NAMES: [&'static str; 4] = ["mir", "llvm-ir", "asm", "obj"];
+
+    pub fn parse(s: &str) -> Result<Emit, String> {
+        match s {
+            "mir" => Ok(Emit::Mir),
+            "llvm-ir" => Ok(Emit::LlvmIr),
+            "asm" => Ok(Emit::Asm),
+            "obj" => Ok(Emit::Obj),
+            _ => Err(format!(
+                "unknown kind of output '{}', expected one of {}",
+                s,
+                Emit::NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Block shared by all failed status checks of the function being generated.
+/// Incoming edges are collected while generating and turned into phis at the end.
+struct ErrorHandler {
+    block: LLVMBasicBlockRef,
+    failed_messages: Vec<(LLVMValueRef, LLVMBasicBlockRef)>,
+    failed_codes: Vec<(LLVMValueRef, LLVMBasicBlockRef)>,
+}
+
+/// How a tracked resource is released when its scope ends.
+#[derive(Clone, Copy)]
+enum Destructor {
+    /// The slot is a bignum released by the backend.
+    Bignum,
+    /// The slot holds a `malloc`ed `i8*` passed to `free`.
+    Free,
+    /// The slot holds a pointer passed to the given deallocator.
+    Func(LLVMValueRef),
+    /// The slot is a hybrid integer whose bignum part is released by the backend.
+    Hybrid,
+}
+
+/// Fields of `hybrid_int`, an `i64` promoted to a bignum once it overflows.
+/// The bignum part is only initialized on the first promotion.
+const HYBRID_IS_BIG: u32 = 0;
+const HYBRID_BIG_READY: u32 = 1;
+const HYBRID_SMALL: u32 = 2;
+const HYBRID_BIG: u32 = 3;
+
+/// Stack slot owning a resource. Slots are zeroed in the entry block,
+/// so releasing one that has not been filled yet is a no-op.
+#[derive(Clone, Copy)]
+struct Resource {
+    slot: LLVMValueRef,
+    dtor: Destructor,
+}
+
 struct LLVMRunner {
     llvm: LLVM,
     funcs: LLVMFuncs,
-    structs: LLVMStructs,
+    bignum: Box<dyn BignumBackend>,
+    hybrid_type: LLVMTypeRef,
+    i64_bounds: Option<(LLVMValueRef, LLVMValueRef, LLVMValueRef)>,
+    error_mode: ErrorMode,
+    error_handler: Option<ErrorHandler>,
+    /// Globals of the messages reported by the error handlers.
+    error_messages: HashMap<String, LLVMValueRef>,
+    unwind_block: Option<LLVMBasicBlockRef>,
+    scopes: Vec<Vec<Resource>>,
+    func_resources: Vec<Resource>,
+    radix: RadixConfig,
+    operands: Operands,
 }
 
 impl LLVMRunner {
-    fn new() -> Self {
-        let mut llvm = LLVM::new();
-        let structs = LLVMStructs::new(&mut llvm);
-        let funcs = LLVMFuncs::new(&mut llvm, &structs);
+    /// Runner generating code for `options`.
+    fn with_options(error_mode: ErrorMode, options: &Options) -> Self {
+        let mut runner = LLVMRunner::with_backend(error_mode, options.backend);
+        runner.radix = options.radix;
+        runner.operands = options.operands.clone();
+        runner.llvm.out_dir = options.out_dir.clone();
+        runner
+    }
+
+    fn with_backend(error_mode: ErrorMode, backend: Backend) -> Self {
+        LLVMRunner::with_llvm(LLVM::new(), error_mode, backend)
+    }
+
+    /// Runner generating into the current module of `llvm`.
+    fn with_llvm(mut llvm: LLVM, error_mode: ErrorMode, backend: Backend) -> Self {
+        let funcs = LLVMFuncs::new(&mut llvm);
+        let bignum = backend.instantiate(&mut llvm);
+        let mut hybrid_fields = [llvm.i8_t(), llvm.i8_t(), llvm.i64_t(), bignum.num_type()];
+        let hybrid_type = llvm.mk_named_struct("hybrid_int", &mut hybrid_fields);
 
         LLVMRunner {
             llvm,
             funcs,
-            structs,
+            bignum,
+            hybrid_type,
+            i64_bounds: None,
+            error_mode,
+            error_handler: None,
+            error_messages: HashMap::new(),
+            unwind_block: None,
+            scopes: vec![],
+            func_resources: vec![],
+            radix: RadixConfig::default(),
+            operands: Operands::Stdin,
         }
     }
 
+    /// Defines the C entry point, returning 0 after the code generated by `f`.
     fn mk_main_func(&mut self, f: fn(&mut LLVMRunner) -> ()) -> LLVMValueRef {
+        let func_type = self.main_func_type();
+        let func = self.llvm.mk_func("main", func_type);
+        self.define_func(func, &mut |r, func| {
+            r.store_main_args(func);
+            f(r);
+            let i32_t = r.llvm.i32_t();
+            Some(r.llvm.const_int(i32_t, 0))
+        });
+        func
+    }
+
+    /// `i32 main(i32 argc, i8** argv)`
+    fn main_func_type(&mut self) -> LLVMTypeRef {
+        let i32_t = self.llvm.i32_t();
+        let argv_t = self.llvm.ptr_t(self.llvm.ptr_t(self.llvm.i8_t()));
+        self.llvm.mk_func_type(i32_t, &mut [i32_t, argv_t])
+    }
+
+    /// Defines the globals holding the arguments of `main` and stores them.
+    fn store_main_args(&mut self, main: LLVMValueRef) {
+        let i32_t = self.llvm.i32_t();
+        let argv_t = self.llvm.ptr_t(self.llvm.ptr_t(self.llvm.i8_t()));
+        let argc = self.llvm.mk_global_var(ARGC_GLOBAL, i32_t, true);
+        let argv = self.llvm.mk_global_var(ARGV_GLOBAL, argv_t, true);
+        let argc_param = self.llvm.get_param(main, 0);
+        let argv_param = self.llvm.get_param(main, 1);
+        self.llvm.build_store(argc_param, argc);
+        self.llvm.build_store(argv_param, argv);
+    }
+
+    /// Defines `void name(params)`; `body` gets the function to read its parameters.
+    fn mk_void_func(
+        &mut self,
+        name: &str,
+        params: &mut [LLVMTypeRef],
+        body: &mut dyn FnMut(&mut LLVMRunner, LLVMValueRef),
+    ) -> LLVMValueRef {
         let ret = self.llvm.void_t();
-        let main_func_type = self.llvm.mk_func_type(ret, &mut vec![]);
-        let main_func = self.llvm.mk_func("main", main_func_type);
-        self.llvm.append_basic_block("entrypoint", main_func);
+        let func_type = self.llvm.mk_func_type(ret, params);
+        let func = self.llvm.mk_func(name, func_type);
+        self.define_func(func, &mut |r, func| {
+            body(r, func);
+            None
+        });
+        func
+    }
+
+    /// Generates the body of the declared `func`. `body` returns the value
+    /// to return, if any, which is returned once the resources are released.
+    fn define_func(
+        &mut self,
+        func: LLVMValueRef,
+        body: &mut dyn FnMut(&mut LLVMRunner, LLVMValueRef) -> Option<LLVMValueRef>,
+    ) {
+        self.llvm.append_basic_block("entrypoint", func);
+        self.push_scope();
+
+        let ret = body(self, func);
+
+        while !self.scopes.is_empty() {
+            self.pop_scope();
+        }
+        match ret {
+            Some(value) => self.llvm.ret(value),
+            None => self.llvm.ret_void(),
+        }
+        self.finish_error_handler();
+        self.func_resources.clear();
+    }
+
+    /// Runs the code generated by `body` `count` times.
+    fn build_counted_loop(&mut self, count: LLVMValueRef, body: &mut dyn FnMut(&mut LLVMRunner)) {
+        let i64_t = self.llvm.i64_t();
+        let counter = self.llvm.build_entry_alloca("counter", i64_t);
+        let func = self.llvm.current_func();
+        let head_block = self.llvm.mk_basic_block("loop_head", func);
+        let body_block = self.llvm.mk_basic_block("loop_body", func);
+        let exit_block = self.llvm.mk_basic_block("loop_exit", func);
+        self.llvm.build_br(head_block);
+
+        self.llvm.position_at_end(head_block);
+        let i = self.llvm.build_load(counter);
+        let more = self.llvm.build_icmp(LLVMIntPredicate::LLVMIntSLT, i, count);
+        self.llvm.build_cond_br(more, body_block, exit_block);
+
+        self.llvm.position_at_end(body_block);
+        body(self);
+        let i = self.llvm.build_load(counter);
+        let one = self.llvm.const_int(i64_t, 1);
+        let next = self.llvm.build_binop(LLVMOpcode::LLVMAdd, i, one);
+        self.llvm.build_store(next, counter);
+        self.llvm.build_br(head_block);
+
+        self.llvm.position_at_end(exit_block);
+    }
+
+    /// `void name(i64 iterations)` computing `(a * b + c - a) / c` with bignums.
+    fn mk_bench_bignum(&mut self, name: &str, operands: [i64; 3]) -> LLVMValueRef {
+        let mut params = [self.llvm.i64_t()];
+        self.mk_void_func(name, &mut params, &mut |r, func| {
+            let consts: Vec<LLVMValueRef> = operands
+                .iter()
+                .map(|&v| {
+                    let value = BigInt::from_i64(v).expect("bignum library failure");
+                    r.bignum_const(&value)
+                })
+                .collect();
+            let (a, b, c) = (consts[0], consts[1], consts[2]);
+            let t = r.alloc_bignum("t");
+            let iterations = r.llvm.get_param(func, 0);
+            r.build_counted_loop(iterations, &mut |r| {
+                r.call_bignum_op(BignumOp::Mul, a, b, t);
+                r.call_bignum_op(BignumOp::Add, t, c, t);
+                r.call_bignum_op(BignumOp::Sub, t, a, t);
+                r.call_bignum_op(BignumOp::Div, t, c, t);
+            });
+        })
+    }
+
+    /// Same as `mk_bench_bignum` with hybrid integers.
+    fn mk_bench_hybrid(&mut self, name: &str, operands: [i64; 3]) -> LLVMValueRef {
+        let mut params = [self.llvm.i64_t()];
+        self.mk_void_func(name, &mut params, &mut |r, func| {
+            let i64_t = r.llvm.i64_t();
+            let mut nums = vec![];
+            for (i, &v) in operands.iter().enumerate() {
+                let num = r.alloc_hybrid(&format!("operand{}", i));
+                let value = r.llvm.const_int(i64_t, v);
+                r.hybrid_set_i64(num, value);
+                nums.push(num);
+            }
+            let (a, b, c) = (nums[0], nums[1], nums[2]);
+            let t = r.alloc_hybrid("t");
+            let iterations = r.llvm.get_param(func, 0);
+            r.build_counted_loop(iterations, &mut |r| {
+                r.call_hybrid_op(BignumOp::Mul, a, b, t);
+                r.call_hybrid_op(BignumOp::Add, t, c, t);
+                r.call_hybrid_op(BignumOp::Sub, t, a, t);
+                r.call_hybrid_op(BignumOp::Div, t, c, t);
+            });
+        })
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    /// Releases everything registered in the innermost scope, newest first.
+    fn pop_scope(&mut self) {
+        if let Some(resources) = self.scopes.pop() {
+            for resource in resources.iter().rev() {
+                self.release(*resource);
+            }
+        }
+    }
+
+    fn alloc_tracked(
+        &mut self,
+        name: &str,
+        type_ref: LLVMTypeRef,
+        dtor: Destructor,
+    ) -> LLVMValueRef {
+        let slot = self.llvm.build_entry_alloca(name, type_ref);
+        let resource = Resource { slot, dtor };
+        self.func_resources.push(resource);
+        match self.scopes.last_mut() {
+            Some(scope) => scope.push(resource),
+            None => panic!("resource '{}' allocated outside of any scope", name),
+        }
+        slot
+    }
+
+    /// Bignum constant computed on the host and embedded into the module,
+    /// for use as a source operand only.
+    fn bignum_const(&mut self, value: &BigInt) -> LLVMValueRef {
+        self.bignum
+            .emit_const(&mut self.llvm, "bignum_const", value)
+    }
+
+    fn alloc_bignum(&mut self, name: &str) -> LLVMValueRef {
+        let num_type = self.bignum.num_type();
+        let num = self.alloc_tracked(name, num_type, Destructor::Bignum);
+        self.call_bignum_init(num);
+        num
+    }
+
+    /// Hybrid integer starting out as a small zero.
+    fn alloc_hybrid(&mut self, name: &str) -> LLVMValueRef {
+        let hybrid_type = self.hybrid_type;
+        self.alloc_tracked(name, hybrid_type, Destructor::Hybrid)
+    }
+
+    /// Releases the resource and resets its slot, so releasing it again
+    /// on an error path is harmless.
+    fn release(&mut self, resource: Resource) {
+        match resource.dtor {
+            Destructor::Bignum => self.bignum.emit_clear(&mut self.llvm, resource.slot),
+            Destructor::Free => {
+                let ptr = self.llvm.build_load(resource.slot);
+                self.call_free(ptr);
+                self.reset_slot(resource.slot);
+            }
+            Destructor::Func(dealloc) => {
+                let ptr = self.llvm.build_load(resource.slot);
+                self.llvm.call_func("", dealloc, &mut vec![ptr]);
+                self.reset_slot(resource.slot);
+            }
+            Destructor::Hybrid => {
+                let big = self.llvm.get_struct_field_ptr(resource.slot, HYBRID_BIG);
+                self.bignum.emit_clear(&mut self.llvm, big);
+                self.reset_slot(resource.slot);
+            }
+        }
+    }
+
+    fn reset_slot(&mut self, slot: LLVMValueRef) {
+        let null = self.llvm.const_null(self.llvm.element_type(slot));
+        self.llvm.build_store(null, slot);
+    }
+
+    fn check_status(&mut self, op: &str, status: LLVMValueRef) {
+        if self.error_mode == ErrorMode::Unchecked {
+            return;
+        }
+        let ok = self.bignum.ok_status();
+        let ok = gen_const(&mut self.llvm, ok as u64);
+        let failed = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntNE, status, ok);
+        let message = format!("{} failed with code %d", op);
+        self.branch_to_error_handler(&message, status, failed);
+    }
+
+    /// Goes to the error handler, which reports `message`, when the `i1`
+    /// `failed` is set.
+    fn check_failed(&mut self, message: &str, failed: LLVMValueRef) {
+        if self.error_mode == ErrorMode::Unchecked {
+            return;
+        }
+        let invalid = self.bignum.invalid_status();
+        let invalid = self.llvm.const_int(self.llvm.i32_t(), i64::from(invalid));
+        self.branch_to_error_handler(message, invalid, failed);
+    }
+
+    /// Goes to the error handler when the `i1` `failed` is set, the `%d`
+    /// of `message` standing for the `i32` `code`, and continues in a new
+    /// block otherwise.
+    fn branch_to_error_handler(&mut self, message: &str, code: LLVMValueRef, failed: LLVMValueRef) {
+        let message = self.error_message(message);
+        let func = self.llvm.current_func();
+        let error_block = match self.error_handler {
+            Some(ref handler) => handler.block,
+            None => {
+                let block = self.llvm.mk_basic_block("error", func);
+                self.error_handler = Some(ErrorHandler {
+                    block,
+                    failed_messages: vec![],
+                    failed_codes: vec![],
+                });
+                block
+            }
+        };
+        let ok_block = self.llvm.mk_basic_block("checked", func);
+        let from = self.llvm.current_block();
+        self.llvm.build_cond_br(failed, error_block, ok_block);
+        if let Some(ref mut handler) = self.error_handler {
+            handler.failed_messages.push((message, from));
+            handler.failed_codes.push((code, from));
+        }
+        self.llvm.position_at_end(ok_block);
+    }
+
+    /// `i8*` to the line reporting `message`, whose global is shared by the
+    /// checks of the module reporting the same.
+    fn error_message(&mut self, message: &str) -> LLVMValueRef {
+        let global = match self.error_messages.get(message) {
+            Some(&global) => global,
+            None => {
+                let text = format!("error: {}\n", message);
+                let global = self.llvm.mk_global_string("error_message", &text);
+                self.error_messages.insert(message.to_owned(), global);
+                global
+            }
+        };
+        self.llvm.get_struct_field_ptr(global, 0)
+    }
+
+    fn finish_error_handler(&mut self) {
+        if let Some(mut handler) = self.error_handler.take() {
+            self.llvm.position_at_end(handler.block);
+            let i8_pt = self.llvm.ptr_t(self.llvm.i8_t());
+            let i32_t = self.llvm.i32_t();
+            let message =
+                self.llvm
+                    .build_phi("failed_message", i8_pt, &mut handler.failed_messages);
+            let code = self
+                .llvm
+                .build_phi("failed_code", i32_t, &mut handler.failed_codes);
+            let stderr_fd = gen_const(&mut self.llvm, 2);
+            self.llvm.call_func(
+                "dprintf",
+                self.funcs.dprintf,
+                &mut vec![stderr_fd, message, code],
+            );
+            if self.error_mode == ErrorMode::Recover {
+                let unwind_block = self.unwind_block();
+                self.llvm.build_br(unwind_block);
+            } else {
+                self.release_func_resources();
+                let exit_code = gen_const(&mut self.llvm, 1);
+                self.llvm
+                    .call_func("", self.funcs.exit, &mut vec![exit_code]);
+                self.llvm.build_unreachable();
+            }
+        }
+        if let Some(unwind_block) = self.unwind_block.take() {
+            self.llvm.position_at_end(unwind_block);
+            self.release_func_resources();
+            let flag = self.failed_flag();
+            let failed = self.llvm.const_int(self.llvm.i8_t(), 1);
+            self.llvm.build_store(failed, flag);
+            let func = self.llvm.current_func();
+            match self.llvm.return_type(func) {
+                Some(ret_type) => {
+                    let none = self.llvm.const_null(ret_type);
+                    self.llvm.ret(none)
+                }
+                None => self.llvm.ret_void(),
+            }
+        }
+    }
+
+    fn release_func_resources(&mut self) {
+        let resources = self.func_resources.clone();
+        for resource in resources.iter().rev() {
+            self.release(*resource);
+        }
+    }
+
+    /// Block of the function being generated which releases its resources
+    /// and returns from it with `FAILED_FLAG` set, in `ErrorMode::Recover`.
+    fn unwind_block(&mut self) -> LLVMBasicBlockRef {
+        match self.unwind_block {
+            Some(block) => block,
+            None => {
+                let func = self.llvm.current_func();
+                let block = self.llvm.mk_basic_block("unwind", func);
+                self.unwind_block = Some(block);
+                block
+            }
+        }
+    }
 
-        f(self);
+    fn failed_flag(&mut self) -> LLVMValueRef {
+        let i8_t = self.llvm.i8_t();
+        self.llvm.get_or_declare_global(FAILED_FLAG, i8_t)
+    }
 
-        self.llvm.ret_void();
-        main_func
+    /// Unwinds in `ErrorMode::Recover` when the generated function just
+    /// called has failed.
+    fn check_callee(&mut self) {
+        if self.error_mode != ErrorMode::Recover {
+            return;
+        }
+        let flag = self.failed_flag();
+        let flag = self.llvm.build_load(flag);
+        let clear = self.llvm.const_int(self.llvm.i8_t(), 0);
+        let failed = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntNE, flag, clear);
+        let unwind_block = self.unwind_block();
+        let func = self.llvm.current_func();
+        let ok_block = self.llvm.mk_basic_block("callee_ok", func);
+        self.llvm.build_cond_br(failed, unwind_block, ok_block);
+        self.llvm.position_at_end(ok_block);
     }
 
     fn call_create_str(&mut self) {
         let func_name = "create_str";
         let i8_pt = self.llvm.ptr_t(self.llvm.i8_t());
-        let create_type = self.llvm.mk_func_type(i8_pt, &mut vec![]);
+        let create_type = self.llvm.mk_func_type(i8_pt, &mut []);
         let create_func = self.llvm.mk_func(func_name, create_type);
         let res = self.llvm.call_func(func_name, create_func, &mut vec![]);
         self.call_printf_func("after calling to create str \n", "");
@@ -116,35 +1125,140 @@ impl LLVMRunner {
     fn call_create_i8(&mut self) {
         let func_name = "create_i8";
         let i8_t = self.llvm.i8_t();
-        let create_type = self.llvm.mk_func_type(i8_t, &mut vec![]);
+        let create_type = self.llvm.mk_func_type(i8_t, &mut []);
         let create_func = self.llvm.mk_func(func_name, create_type);
         let res = self.llvm.call_func(func_name, create_func, &mut vec![]);
         self.call_printf_func("after calling to create i8 \n", "");
         self.call_printf_func_by_value("i8 value: %d\n", res);
     }
 
-    fn call_mp_init(&mut self, num: LLVMValueRef) {
-        self.llvm
-            .call_func("mp_init", self.funcs.mp_init, &mut vec![num]);
+    fn call_bignum_init(&mut self, num: LLVMValueRef) {
+        let status = self.bignum.emit_init(&mut self.llvm, num);
+        self.check_status("init", status);
+    }
+
+    /// Parses `num_ptr` into `num` and returns the status of the parsing,
+    /// which is checked already.
+    fn call_bignum_parse(
+        &mut self,
+        num: LLVMValueRef,
+        num_ptr: LLVMValueRef,
+        radix: InputRadix,
+    ) -> LLVMValueRef {
+        match radix {
+            InputRadix::Fixed(radix) => {
+                let radix = gen_const(&mut self.llvm, u64::from(radix));
+                let status = self.bignum.emit_parse(&mut self.llvm, num, num_ptr, radix);
+                self.check_status("parse", status);
+                status
+            }
+            InputRadix::Prefixed => self.call_bignum_parse_prefixed(num, num_ptr),
+        }
     }
 
-    fn call_mp_read_radix(&mut self, num: LLVMValueRef, str_num: LLVMValueRef) {
-        let const_10 = gen_const(&mut self.llvm, 10);
-        let num_ptr = self.llvm.get_struct_field_ptr(str_num, 0);
-        self.llvm.call_func(
-            "mp_read_radix",
-            self.funcs.mp_read_radix,
-            &mut vec![num, num_ptr, const_10],
+    /// Parses an optionally signed number whose radix is chosen by a
+    /// `0x`, `0o` or `0b` prefix, decimal without one.
+    fn call_bignum_parse_prefixed(
+        &mut self,
+        num: LLVMValueRef,
+        str_ptr: LLVMValueRef,
+    ) -> LLVMValueRef {
+        let i8_t = self.llvm.i8_t();
+        let i32_t = self.llvm.i32_t();
+        let i64_t = self.llvm.i64_t();
+        let minus = self.llvm.const_int(i8_t, i64::from(b'-'));
+        let first = self.llvm.build_load(str_ptr);
+        let neg = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntEQ, first, minus);
+        let sign_len = self.llvm.zero_extend(neg, i64_t);
+        let unsigned_ptr = self.llvm.build_offset(str_ptr, sign_len);
+        let lead = self.llvm.build_load(unsigned_ptr);
+        let zero_char = self.llvm.const_int(i8_t, i64::from(b'0'));
+        let has_zero = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntEQ, lead, zero_char);
+
+        let func = self.llvm.current_func();
+        let prefix_block = self.llvm.mk_basic_block("radix_prefix", func);
+        let radix_block = self.llvm.mk_basic_block("radix_selected", func);
+        let no_zero_block = self.llvm.current_block();
+        let decimal = gen_const(&mut self.llvm, 10);
+        self.llvm.build_cond_br(has_zero, prefix_block, radix_block);
+
+        self.llvm.position_at_end(prefix_block);
+        let one = self.llvm.const_int(i64_t, 1);
+        let marker_ptr = self.llvm.build_offset(unsigned_ptr, one);
+        let marker = self.llvm.build_load(marker_ptr);
+        let lower_case_bit = self.llvm.const_int(i8_t, 0x20);
+        let marker = self.llvm.build_or(marker, lower_case_bit);
+        let mut prefixed_radix = decimal;
+        for &(ch, radix) in [(b'b', 2), (b'o', 8), (b'x', 16)].iter() {
+            let ch = self.llvm.const_int(i8_t, i64::from(ch));
+            let is_marker = self
+                .llvm
+                .build_icmp(LLVMIntPredicate::LLVMIntEQ, marker, ch);
+            let radix = self.llvm.const_int(i32_t, radix);
+            prefixed_radix = self.llvm.build_select(is_marker, radix, prefixed_radix);
+        }
+        self.llvm.build_br(radix_block);
+
+        self.llvm.position_at_end(radix_block);
+        let radix = self.llvm.build_phi(
+            "radix",
+            i32_t,
+            &mut [(decimal, no_zero_block), (prefixed_radix, prefix_block)],
         );
+        let has_prefix = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntNE, radix, decimal);
+        let two = self.llvm.const_int(i64_t, 2);
+        let none = self.llvm.const_int(i64_t, 0);
+        let prefix_len = self.llvm.build_select(has_prefix, two, none);
+        let digits_ptr = self.llvm.build_offset(unsigned_ptr, prefix_len);
+        let status = self
+            .bignum
+            .emit_parse(&mut self.llvm, num, digits_ptr, radix);
+        // like `BigInt::from_str_prefixed`, which takes a single sign and
+        // some digits, rather than the bignum library
+        let first_digit = self.llvm.build_load(digits_ptr);
+        let signed = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntEQ, first_digit, minus);
+        let nul = self.llvm.const_int(i8_t, 0);
+        let empty = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntEQ, first_digit, nul);
+        let malformed = self.llvm.build_or(signed, empty);
+        let invalid = self
+            .llvm
+            .const_int(i32_t, i64::from(self.bignum.invalid_status()));
+        let status = self.llvm.build_select(malformed, invalid, status);
+        self.check_status("parse", status);
+
+        let neg_block = self.llvm.mk_basic_block("parsed_negative", func);
+        let done_block = self.llvm.mk_basic_block("parsed", func);
+        self.llvm.build_cond_br(neg, neg_block, done_block);
+        self.llvm.position_at_end(neg_block);
+        let neg_status = self.bignum.emit_neg(&mut self.llvm, num, num);
+        self.check_status("neg", neg_status);
+        self.llvm.build_br(done_block);
+        self.llvm.position_at_end(done_block);
+        status
     }
 
-    fn call_radix_size(&mut self, num: LLVMValueRef, str_size_ref: LLVMValueRef) -> LLVMValueRef {
-        let const_10 = gen_const(&mut self.llvm, 10);
-        self.llvm.call_func(
-            "mp_radix_size",
-            self.funcs.mp_radix_size,
-            &mut vec![num, const_10, str_size_ref],
-        )
+    fn call_bignum_format_size(
+        &mut self,
+        num: LLVMValueRef,
+        str_size_ref: LLVMValueRef,
+        radix: u32,
+    ) -> LLVMValueRef {
+        let radix = gen_const(&mut self.llvm, u64::from(radix));
+        let status = self
+            .bignum
+            .emit_format_size(&mut self.llvm, num, radix, str_size_ref);
+        self.check_status("format_size", status);
+        status
     }
 
     fn call_malloc(&mut self, size: LLVMValueRef) -> LLVMValueRef {
@@ -155,37 +1269,545 @@ impl LLVMRunner {
             .call_func("malloc", self.funcs.malloc, &mut vec![extended])
     }
 
-    fn call_mp_toradix(
+    fn call_bignum_format(
         &mut self,
         str_ptr: LLVMValueRef,
         num: LLVMValueRef,
         res_str: LLVMValueRef,
+        radix: u32,
     ) -> LLVMValueRef {
         self.llvm.build_store(str_ptr, res_str);
         let loaded = self.llvm.build_load(res_str);
-        let const_10 = gen_const(&mut self.llvm, 10);
-        self.llvm.call_func(
-            "mp_toradix",
-            self.funcs.mp_toradix,
-            &mut vec![num, loaded, const_10],
-        )
+        let radix = gen_const(&mut self.llvm, u64::from(radix));
+        let status = self.bignum.emit_format(&mut self.llvm, num, loaded, radix);
+        self.check_status("format", status);
+        status
+    }
+
+    fn call_bignum_op(
+        &mut self,
+        op: BignumOp,
+        num1: LLVMValueRef,
+        num2: LLVMValueRef,
+        res_num_ref: LLVMValueRef,
+    ) {
+        let status = self
+            .bignum
+            .emit_binary(&mut self.llvm, op, num1, num2, res_num_ref);
+        self.check_status(op.name(), status);
+    }
+
+    fn call_bignum_set_i64(&mut self, num: LLVMValueRef, value: LLVMValueRef) {
+        let i64_t = self.llvm.i64_t();
+        let zero = self.llvm.const_int(i64_t, 0);
+        let neg = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntSLT, value, zero);
+        // i64::MIN negates to itself, which read as unsigned is its magnitude
+        let negated = self.llvm.build_binop(LLVMOpcode::LLVMSub, zero, value);
+        let magnitude = self.llvm.build_select(neg, negated, value);
+        let status = self.bignum.emit_set_u64(&mut self.llvm, num, magnitude);
+        self.check_status("set", status);
+
+        let func = self.llvm.current_func();
+        let neg_block = self.llvm.mk_basic_block("set_negative", func);
+        let done_block = self.llvm.mk_basic_block("set", func);
+        self.llvm.build_cond_br(neg, neg_block, done_block);
+        self.llvm.position_at_end(neg_block);
+        let status = self.bignum.emit_neg(&mut self.llvm, num, num);
+        self.check_status("neg", status);
+        self.llvm.build_br(done_block);
+        self.llvm.position_at_end(done_block);
+    }
+
+    fn store_hybrid_field(&mut self, hybrid: LLVMValueRef, field: u32, value: i64) {
+        let field_ptr = self.llvm.get_struct_field_ptr(hybrid, field);
+        let field_type = self.llvm.element_type(field_ptr);
+        let value = self.llvm.const_int(field_type, value);
+        self.llvm.build_store(value, field_ptr);
+    }
+
+    fn hybrid_is_big(&mut self, hybrid: LLVMValueRef) -> LLVMValueRef {
+        let tag_ptr = self.llvm.get_struct_field_ptr(hybrid, HYBRID_IS_BIG);
+        let tag = self.llvm.build_load(tag_ptr);
+        let small = self.llvm.const_int(self.llvm.i8_t(), 0);
+        self.llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntNE, tag, small)
+    }
+
+    fn hybrid_set_i64(&mut self, hybrid: LLVMValueRef, value: LLVMValueRef) {
+        let small_ptr = self.llvm.get_struct_field_ptr(hybrid, HYBRID_SMALL);
+        self.llvm.build_store(value, small_ptr);
+        self.store_hybrid_field(hybrid, HYBRID_IS_BIG, 0);
+    }
+
+    /// Bignum part of `hybrid`, initialized if this is its first use.
+    fn hybrid_big(&mut self, hybrid: LLVMValueRef) -> LLVMValueRef {
+        let big = self.llvm.get_struct_field_ptr(hybrid, HYBRID_BIG);
+        let ready_ptr = self.llvm.get_struct_field_ptr(hybrid, HYBRID_BIG_READY);
+        let ready = self.llvm.build_load(ready_ptr);
+        let not_ready = self.llvm.const_int(self.llvm.i8_t(), 0);
+        let is_ready = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntNE, ready, not_ready);
+        let func = self.llvm.current_func();
+        let init_block = self.llvm.mk_basic_block("hybrid_init", func);
+        let done_block = self.llvm.mk_basic_block("hybrid_ready", func);
+        self.llvm.build_cond_br(is_ready, done_block, init_block);
+        self.llvm.position_at_end(init_block);
+        self.call_bignum_init(big);
+        self.store_hybrid_field(hybrid, HYBRID_BIG_READY, 1);
+        self.llvm.build_br(done_block);
+        self.llvm.position_at_end(done_block);
+        big
+    }
+
+    /// Bignum holding the value of `hybrid`, promoting a small value
+    /// without changing the representation `hybrid` is in.
+    fn hybrid_to_bignum(&mut self, hybrid: LLVMValueRef) -> LLVMValueRef {
+        let big = self.hybrid_big(hybrid);
+        let is_big = self.hybrid_is_big(hybrid);
+        let func = self.llvm.current_func();
+        let promote_block = self.llvm.mk_basic_block("hybrid_promote", func);
+        let done_block = self.llvm.mk_basic_block("hybrid_promoted", func);
+        self.llvm.build_cond_br(is_big, done_block, promote_block);
+        self.llvm.position_at_end(promote_block);
+        let small_ptr = self.llvm.get_struct_field_ptr(hybrid, HYBRID_SMALL);
+        let small = self.llvm.build_load(small_ptr);
+        self.call_bignum_set_i64(big, small);
+        self.llvm.build_br(done_block);
+        self.llvm.position_at_end(done_block);
+        big
+    }
+
+    /// Hybrid integer set to `value`.
+    fn hybrid_literal(&mut self, value: &BigInt) -> LLVMValueRef {
+        let hybrid = self.alloc_hybrid("literal");
+        self.hybrid_set_const(hybrid, value);
+        hybrid
+    }
+
+    /// Sets `hybrid` to `value`, which is embedded as a constant when it
+    /// doesn't fit into an `i64`.
+    fn hybrid_set_const(&mut self, hybrid: LLVMValueRef, value: &BigInt) {
+        match value.to_i64() {
+            Some(v) => {
+                let small = self.llvm.const_int(self.llvm.i64_t(), v);
+                self.hybrid_set_i64(hybrid, small);
+            }
+            None => {
+                let big = self.hybrid_big(hybrid);
+                let value = self.bignum_const(value);
+                let status = self.bignum.emit_copy(&mut self.llvm, value, big);
+                self.check_status("copy", status);
+                self.store_hybrid_field(hybrid, HYBRID_IS_BIG, 1);
+            }
+        }
+    }
+
+    /// Parses a number into each of `hybrids`, from where `operands` says.
+    fn read_operands(&mut self, hybrids: &[LLVMValueRef]) {
+        let radix = self.radix.input;
+        match self.operands.clone() {
+            Operands::Stdin => {
+                for &hybrid in hybrids {
+                    self.call_read_hybrid(hybrid);
+                }
+            }
+            Operands::Args => {
+                let i64_t = self.llvm.i64_t();
+                for (i, &hybrid) in hybrids.iter().enumerate() {
+                    // after the program's name
+                    let index = self.llvm.const_int(i64_t, i as i64 + 1);
+                    self.call_parse_arg(hybrid, index);
+                }
+            }
+            Operands::File(path) => {
+                let file = self.call_fopen(&path);
+                for &hybrid in hybrids {
+                    self.call_read_token(hybrid, file);
+                }
+                self.call_fclose(file);
+            }
+            Operands::Given(values) => {
+                for (&hybrid, value) in hybrids.iter().zip(&values) {
+                    let value_str = self.llvm.mk_global_string("operand", value);
+                    let value_ptr = self.llvm.get_struct_field_ptr(value_str, 0);
+                    self.call_hybrid_parse(hybrid, value_ptr, radix);
+                }
+            }
+        }
+    }
+
+    /// Opens `path` for reading, ending the program with the reason when
+    /// it can't.
+    fn call_fopen(&mut self, path: &Path) -> LLVMValueRef {
+        let i8_pt = self.llvm.ptr_t(self.llvm.i8_t());
+        let fopen_type = self.llvm.mk_func_type(i8_pt, &mut [i8_pt, i8_pt]);
+        let fopen = self.llvm.get_or_mk_func("fopen", fopen_type);
+        let path_str = self
+            .llvm
+            .mk_global_string("input_path", &path.to_string_lossy());
+        let path_ptr = self.llvm.get_struct_field_ptr(path_str, 0);
+        let mode_str = self.llvm.mk_global_string("input_mode", "r");
+        let mode_ptr = self.llvm.get_struct_field_ptr(mode_str, 0);
+        let file = self
+            .llvm
+            .call_func("input_file", fopen, &mut vec![path_ptr, mode_ptr]);
+
+        let null = self.llvm.const_null(i8_pt);
+        let failed = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntEQ, file, null);
+        let func = self.llvm.current_func();
+        let failed_block = self.llvm.mk_basic_block("fopen_failed", func);
+        let ok_block = self.llvm.mk_basic_block("fopen_ok", func);
+        self.llvm.build_cond_br(failed, failed_block, ok_block);
+        self.llvm.position_at_end(failed_block);
+        let void_t = self.llvm.void_t();
+        let perror_type = self.llvm.mk_func_type(void_t, &mut [i8_pt]);
+        let perror = self.llvm.get_or_mk_func("perror", perror_type);
+        let message = format!("error: can't open {}", path.display());
+        let message_str = self.llvm.mk_global_string("fopen_error", &message);
+        let message_ptr = self.llvm.get_struct_field_ptr(message_str, 0);
+        self.llvm.call_func("", perror, &mut vec![message_ptr]);
+        let exit_code = gen_const(&mut self.llvm, 1);
+        self.llvm
+            .call_func("", self.funcs.exit, &mut vec![exit_code]);
+        self.llvm.build_unreachable();
+        self.llvm.position_at_end(ok_block);
+        file
     }
 
-    fn call_mp_add(&mut self, num1: LLVMValueRef, num2: LLVMValueRef, res_num_ref: LLVMValueRef) {
-        self.llvm.call_func(
-            "mp_add",
-            self.funcs.mp_add,
-            &mut vec![num1, num2, res_num_ref],
+    /// Parses the next whitespace delimited token of `file`, the standard
+    /// input when null, into `hybrid`, failing at the end of the file.
+    fn call_read_token(&mut self, hybrid: LLVMValueRef, file: LLVMValueRef) {
+        let i8_pt = self.llvm.ptr_t(self.llvm.i8_t());
+        let i64_t = self.llvm.i64_t();
+        let i32_t = self.llvm.i32_t();
+        let read_type = self.llvm.mk_func_type(
+            i32_t,
+            &mut [i8_pt, i64_t, self.llvm.ptr_t(i8_pt), self.llvm.ptr_t(i64_t)],
+        );
+        let read = self.llvm.get_or_mk_func("calc_read_token", read_type);
+        let token_slot = self.alloc_tracked("input_token", i8_pt, Destructor::Free);
+        let len_ref = self.llvm.build_entry_alloca("input_len", i64_t);
+        let max_len = self.llvm.const_int(i64_t, MAX_INPUT_LEN);
+        let status = self.llvm.call_func(
+            "read_status",
+            read,
+            &mut vec![file, max_len, token_slot, len_ref],
         );
+        self.check_read_status(status);
+        let token = self.llvm.build_load(token_slot);
+        let radix = self.radix.input;
+        self.call_hybrid_parse(hybrid, token, radix);
+        self.release(Resource {
+            slot: token_slot,
+            dtor: Destructor::Free,
+        });
+    }
+
+    /// Goes to the error handler unless the `i32` `status` of the input
+    /// runtime is `CALC_INPUT_OK`.
+    fn check_read_status(&mut self, status: LLVMValueRef) {
+        let i32_t = self.llvm.i32_t();
+        let too_long_message = format!("input number longer than {} digits", MAX_INPUT_LEN);
+        let failures = [
+            (input::CALC_INPUT_EOF, "unexpected end of input"),
+            (input::CALC_INPUT_TOO_LONG, too_long_message.as_str()),
+        ];
+        for &(code, message) in failures.iter() {
+            let code = self.llvm.const_int(i32_t, i64::from(code));
+            let failed = self
+                .llvm
+                .build_icmp(LLVMIntPredicate::LLVMIntEQ, status, code);
+            self.check_failed(message, failed);
+        }
+        let ok = self.llvm.const_int(i32_t, i64::from(input::CALC_INPUT_OK));
+        let failed = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntNE, status, ok);
+        if self.error_mode != ErrorMode::Unchecked {
+            self.branch_to_error_handler("read failed with code %d", status, failed);
+        }
+    }
+
+    fn call_fclose(&mut self, file: LLVMValueRef) {
+        let i8_pt = self.llvm.ptr_t(self.llvm.i8_t());
+        let i32_t = self.llvm.i32_t();
+        let fclose_type = self.llvm.mk_func_type(i32_t, &mut [i8_pt]);
+        let fclose = self.llvm.get_or_mk_func("fclose", fclose_type);
+        self.llvm.call_func("", fclose, &mut vec![file]);
+    }
+
+    /// Reads a whitespace delimited number from the standard input into `hybrid`.
+    fn call_read_hybrid(&mut self, hybrid: LLVMValueRef) {
+        let stdin = self.llvm.const_null(self.llvm.ptr_t(self.llvm.i8_t()));
+        self.call_read_token(hybrid, stdin);
+    }
+
+    /// Prints `hybrid` in the output radix followed by a newline.
+    fn call_print_hybrid(&mut self, hybrid: LLVMValueRef) {
+        let num = self.hybrid_to_bignum(hybrid);
+        let i8_pt = self.llvm.ptr_t(self.llvm.i8_t());
+        let i32_t = self.llvm.i32_t();
+        let str_size_ref = self.llvm.build_entry_alloca("str_size", i32_t);
+        let str_slot = self.alloc_tracked("print_str", i8_pt, Destructor::Free);
+        let radix = self.radix.output;
+        self.call_bignum_format_size(num, str_size_ref, radix);
+        let str_ptr = self.call_malloc(str_size_ref);
+        self.call_bignum_format(str_ptr, num, str_slot, radix);
+        let loaded = self.llvm.build_load(str_slot);
+        self.call_printf_func_by_value("%s\n", loaded);
+        self.release(Resource {
+            slot: str_slot,
+            dtor: Destructor::Free,
+        });
+    }
+
+    /// Bignum constants `i64::MIN`, zero and `i64::MAX`, shared by the module.
+    fn i64_bounds(&mut self) -> (LLVMValueRef, LLVMValueRef, LLVMValueRef) {
+        if let Some(bounds) = self.i64_bounds {
+            return bounds;
+        }
+        let mut consts = [i64::MIN, 0, i64::MAX]
+            .iter()
+            .map(|&v| {
+                let value = BigInt::from_i64(v).expect("bignum library failure");
+                self.bignum_const(&value)
+            })
+            .collect::<Vec<_>>();
+        let max = consts.pop().unwrap();
+        let zero = consts.pop().unwrap();
+        let min = consts.pop().unwrap();
+        self.i64_bounds = Some((min, zero, max));
+        (min, zero, max)
+    }
+
+    /// Parses into the bignum part and switches back to the small one
+    /// when the number fits into an `i64`.
+    fn call_hybrid_parse(
+        &mut self,
+        hybrid: LLVMValueRef,
+        num_ptr: LLVMValueRef,
+        radix: InputRadix,
+    ) {
+        let big = self.hybrid_big(hybrid);
+        self.call_bignum_parse(big, num_ptr, radix);
+        self.store_hybrid_field(hybrid, HYBRID_IS_BIG, 1);
+        self.hybrid_demote(hybrid);
+    }
+
+    /// Switches `hybrid` back to its small representation when it is a
+    /// bignum whose value fits into an `i64`.
+    fn hybrid_demote(&mut self, hybrid: LLVMValueRef) {
+        let is_big = self.hybrid_is_big(hybrid);
+        let func = self.llvm.current_func();
+        let check_block = self.llvm.mk_basic_block("hybrid_check_fits", func);
+        let demote_block = self.llvm.mk_basic_block("hybrid_demote", func);
+        let done_block = self.llvm.mk_basic_block("hybrid_demoted", func);
+        self.llvm.build_cond_br(is_big, check_block, done_block);
+
+        self.llvm.position_at_end(check_block);
+        let big = self.llvm.get_struct_field_ptr(hybrid, HYBRID_BIG);
+        let (min, zero, max) = self.i64_bounds();
+        let i32_t = self.llvm.i32_t();
+        let eq = self
+            .llvm
+            .const_int(i32_t, i64::from(self.bignum.cmp_equal()));
+        let above_min = self.bignum.emit_cmp(&mut self.llvm, big, min);
+        let above_min = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntSGE, above_min, eq);
+        let below_max = self.bignum.emit_cmp(&mut self.llvm, big, max);
+        let below_max = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntSLE, below_max, eq);
+        let fits = self.llvm.build_and(above_min, below_max);
+        self.llvm.build_cond_br(fits, demote_block, done_block);
+
+        self.llvm.position_at_end(demote_block);
+        let magnitude = self.bignum.emit_get_u64(&mut self.llvm, big);
+        let sign = self.bignum.emit_cmp(&mut self.llvm, big, zero);
+        let neg = self.llvm.build_icmp(LLVMIntPredicate::LLVMIntSLT, sign, eq);
+        let i64_t = self.llvm.i64_t();
+        let none = self.llvm.const_int(i64_t, 0);
+        let negated = self.llvm.build_binop(LLVMOpcode::LLVMSub, none, magnitude);
+        let value = self.llvm.build_select(neg, negated, magnitude);
+        self.hybrid_set_i64(hybrid, value);
+        self.llvm.build_br(done_block);
+        self.llvm.position_at_end(done_block);
+    }
+
+    /// Copies the value of `from` into `to`.
+    fn call_hybrid_copy(&mut self, from: LLVMValueRef, to: LLVMValueRef) {
+        let is_big = self.hybrid_is_big(from);
+        let func = self.llvm.current_func();
+        let small_block = self.llvm.mk_basic_block("copy_small", func);
+        let big_block = self.llvm.mk_basic_block("copy_big", func);
+        let done_block = self.llvm.mk_basic_block("copied", func);
+        self.llvm.build_cond_br(is_big, big_block, small_block);
+
+        self.llvm.position_at_end(small_block);
+        let small_ptr = self.llvm.get_struct_field_ptr(from, HYBRID_SMALL);
+        let small = self.llvm.build_load(small_ptr);
+        self.hybrid_set_i64(to, small);
+        self.llvm.build_br(done_block);
+
+        self.llvm.position_at_end(big_block);
+        let from_big = self.llvm.get_struct_field_ptr(from, HYBRID_BIG);
+        let to_big = self.hybrid_big(to);
+        let status = self.bignum.emit_copy(&mut self.llvm, from_big, to_big);
+        self.check_status("copy", status);
+        self.store_hybrid_field(to, HYBRID_IS_BIG, 1);
+        self.llvm.build_br(done_block);
+
+        self.llvm.position_at_end(done_block);
+    }
+
+    /// `op` on two `i64`s, returning the result and an `i1` set when it
+    /// can't be represented, in which case the result is meaningless.
+    fn build_small_op(
+        &mut self,
+        op: BignumOp,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+    ) -> (LLVMValueRef, LLVMValueRef) {
+        let i64_t = self.llvm.i64_t();
+        let intrinsic = match op {
+            BignumOp::Add => "llvm.sadd.with.overflow.i64",
+            BignumOp::Sub => "llvm.ssub.with.overflow.i64",
+            BignumOp::Mul => "llvm.smul.with.overflow.i64",
+            BignumOp::Div | BignumOp::Rem => {
+                // division by zero and i64::MIN / -1 are left to the bignum path
+                let zero = self.llvm.const_int(i64_t, 0);
+                let minus_one = self.llvm.const_int(i64_t, -1);
+                let one = self.llvm.const_int(i64_t, 1);
+                let min = self.llvm.const_int(i64_t, i64::MIN);
+                let by_zero = self.llvm.build_icmp(LLVMIntPredicate::LLVMIntEQ, rhs, zero);
+                let lhs_min = self.llvm.build_icmp(LLVMIntPredicate::LLVMIntEQ, lhs, min);
+                let rhs_minus_one =
+                    self.llvm
+                        .build_icmp(LLVMIntPredicate::LLVMIntEQ, rhs, minus_one);
+                let min_by_minus_one = self.llvm.build_and(lhs_min, rhs_minus_one);
+                let overflow = self.llvm.build_or(by_zero, min_by_minus_one);
+                let divisor = self.llvm.build_select(overflow, one, rhs);
+                let opcode = if op == BignumOp::Div {
+                    LLVMOpcode::LLVMSDiv
+                } else {
+                    LLVMOpcode::LLVMSRem
+                };
+                let value = self.llvm.build_binop(opcode, lhs, divisor);
+                return (value, overflow);
+            }
+        };
+        let i1_t = self.llvm.i1_t();
+        let ret = self.llvm.struct_t(&mut [i64_t, i1_t]);
+        let func_type = self.llvm.mk_func_type(ret, &mut [i64_t, i64_t]);
+        let func = self.llvm.get_or_mk_func(intrinsic, func_type);
+        let res = self.llvm.call_func(op.name(), func, &mut vec![lhs, rhs]);
+        let value = self.llvm.build_extract_value(res, 0);
+        let overflow = self.llvm.build_extract_value(res, 1);
+        (value, overflow)
+    }
+
+    /// `res = lhs op rhs` on hybrid integers. Small operands go through
+    /// the overflow checking `i64` instructions; big operands or an
+    /// overflow fall back to the bignum backend.
+    fn call_hybrid_op(
+        &mut self,
+        op: BignumOp,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+        res: LLVMValueRef,
+    ) {
+        let func = self.llvm.current_func();
+        let fast_block = self.llvm.mk_basic_block("hybrid_fast", func);
+        let small_block = self.llvm.mk_basic_block("hybrid_small", func);
+        let slow_block = self.llvm.mk_basic_block("hybrid_slow", func);
+        let done_block = self.llvm.mk_basic_block("hybrid_done", func);
+        let lhs_big = self.hybrid_is_big(lhs);
+        let rhs_big = self.hybrid_is_big(rhs);
+        let any_big = self.llvm.build_or(lhs_big, rhs_big);
+        self.llvm.build_cond_br(any_big, slow_block, fast_block);
+
+        self.llvm.position_at_end(fast_block);
+        let lhs_small_ptr = self.llvm.get_struct_field_ptr(lhs, HYBRID_SMALL);
+        let lhs_small = self.llvm.build_load(lhs_small_ptr);
+        let rhs_small_ptr = self.llvm.get_struct_field_ptr(rhs, HYBRID_SMALL);
+        let rhs_small = self.llvm.build_load(rhs_small_ptr);
+        let (value, overflow) = self.build_small_op(op, lhs_small, rhs_small);
+        self.llvm.build_cond_br(overflow, slow_block, small_block);
+
+        self.llvm.position_at_end(small_block);
+        self.hybrid_set_i64(res, value);
+        self.llvm.build_br(done_block);
+
+        self.llvm.position_at_end(slow_block);
+        let lhs_num = self.hybrid_to_bignum(lhs);
+        let rhs_num = self.hybrid_to_bignum(rhs);
+        let res_num = self.hybrid_big(res);
+        self.call_bignum_op(op, lhs_num, rhs_num, res_num);
+        self.store_hybrid_field(res, HYBRID_IS_BIG, 1);
+        // so that the operations which follow take the fast path again
+        self.hybrid_demote(res);
+        self.llvm.build_br(done_block);
+
+        self.llvm.position_at_end(done_block);
+    }
+
+    /// `lhs pred rhs` on hybrid integers as an `i1`, `pred` being signed.
+    /// Bignums are compared with `mp_cmp`, whose result has the sign of
+    /// the difference.
+    fn call_hybrid_cmp(
+        &mut self,
+        pred: LLVMIntPredicate,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+    ) -> LLVMValueRef {
+        let func = self.llvm.current_func();
+        let fast_block = self.llvm.mk_basic_block("cmp_small", func);
+        let slow_block = self.llvm.mk_basic_block("cmp_big", func);
+        let done_block = self.llvm.mk_basic_block("cmp_done", func);
+        let lhs_big = self.hybrid_is_big(lhs);
+        let rhs_big = self.hybrid_is_big(rhs);
+        let any_big = self.llvm.build_or(lhs_big, rhs_big);
+        self.llvm.build_cond_br(any_big, slow_block, fast_block);
+
+        self.llvm.position_at_end(fast_block);
+        let lhs_small_ptr = self.llvm.get_struct_field_ptr(lhs, HYBRID_SMALL);
+        let lhs_small = self.llvm.build_load(lhs_small_ptr);
+        let rhs_small_ptr = self.llvm.get_struct_field_ptr(rhs, HYBRID_SMALL);
+        let rhs_small = self.llvm.build_load(rhs_small_ptr);
+        let small_res = self.llvm.build_icmp(pred, lhs_small, rhs_small);
+        self.llvm.build_br(done_block);
+
+        self.llvm.position_at_end(slow_block);
+        let lhs_num = self.hybrid_to_bignum(lhs);
+        let rhs_num = self.hybrid_to_bignum(rhs);
+        let ordering = self.bignum.emit_cmp(&mut self.llvm, lhs_num, rhs_num);
+        let eq = self.bignum.cmp_equal();
+        let eq = self.llvm.const_int(self.llvm.i32_t(), i64::from(eq));
+        let big_res = self.llvm.build_icmp(pred, ordering, eq);
+        let big_from = self.llvm.current_block();
+        self.llvm.build_br(done_block);
+
+        self.llvm.position_at_end(done_block);
+        let i1_t = self.llvm.i1_t();
+        self.llvm.build_phi(
+            "cmp",
+            i1_t,
+            &mut [(small_res, fast_block), (big_res, big_from)],
+        )
     }
 
     fn call_hello_world_function(&mut self) {
         let func_name = "hello_world";
         let ret = self.llvm.void_t();
-        let hello_world_func_type = self.llvm.mk_func_type(ret, &mut vec![]);
+        let hello_world_func_type = self.llvm.mk_func_type(ret, &mut []);
         let hello_world_func = self.llvm.mk_func(func_name, hello_world_func_type);
-        self.llvm
-            .call_func(func_name, hello_world_func, &mut vec![]);
+        self.llvm.call_func("", hello_world_func, &mut vec![]);
     }
 
     fn call_printf_func_by_value(&mut self, fmt: &str, value: LLVMValueRef) {
@@ -200,7 +1822,7 @@ impl LLVMRunner {
         let format_str = self.llvm.mk_global_string("format", fmt);
         let value_str = self.llvm.mk_global_string("value", value);
         let format_str_loaded = self.llvm.get_struct_field_ptr(format_str, 0);
-        let value_ptr = self.llvm.build_load(value_str);
+        let value_ptr = self.llvm.get_struct_field_ptr(value_str, 0);
         let mut printf_args = vec![format_str_loaded, value_ptr];
         self.llvm
             .call_func("printf", self.funcs.printf, &mut printf_args);
@@ -214,37 +1836,37 @@ impl LLVMRunner {
             .call_func("printf", self.funcs.printf, &mut printf_args);
     }
 
-    fn call_scanf_func(&mut self, fmt: &str, value: LLVMValueRef) {
-        let format_str = self.llvm.mk_global_string("format", fmt);
-        let format_str_loaded = self.llvm.get_struct_field_ptr(format_str, 0);
-        let loaded_value_buf = self.llvm.get_struct_field_ptr(value, 0);
-        let mut printf_args = vec![format_str_loaded, loaded_value_buf];
-        self.llvm
-            .call_func("scanf", self.funcs.scanf, &mut printf_args);
-    }
-
     fn call_free(&mut self, addr: LLVMValueRef) {
-        self.llvm
-            .call_func("free", self.funcs.free, &mut vec![addr]);
+        self.llvm.call_func("", self.funcs.free, &mut vec![addr]);
     }
 
     fn call_test(&mut self) {
         let test_struct = self.llvm.struct_test();
-        let func_type = self.llvm.mk_func_type(test_struct, &mut vec![]);
+        let func_type = self.llvm.mk_func_type(test_struct, &mut []);
         let func = self.llvm.mk_func("create_test", func_type);
+        let free_func = self.mk_dealloc_func("free_test", test_struct);
+        let res_slot = self.alloc_tracked("test", test_struct, Destructor::Func(free_func));
         let res = self.llvm.call_func("create_test", func, &mut vec![]);
+        self.llvm.build_store(res, res_slot);
         let field_ptr = self.llvm.get_struct_field_ptr(res, 1);
         let field_val = self.llvm.load_field_by_ptr(field_ptr);
         self.call_printf_func_by_value("create_test: %d\n", field_val);
+    }
 
-        self.call_free(res);
+    fn mk_dealloc_func(&mut self, name: &str, ptr_type: LLVMTypeRef) -> LLVMValueRef {
+        let ret = self.llvm.void_t();
+        let func_type = self.llvm.mk_func_type(ret, &mut [ptr_type]);
+        self.llvm.mk_func(name, func_type)
     }
 
     fn call_slice(&mut self) {
         let array_test = self.llvm.array_test();
-        let func_type = self.llvm.mk_func_type(array_test, &mut vec![]);
+        let func_type = self.llvm.mk_func_type(array_test, &mut []);
         let func = self.llvm.mk_func("create_slice", func_type);
+        let free_func = self.mk_dealloc_func("free_slice", array_test);
+        let res_slot = self.alloc_tracked("slice", array_test, Destructor::Func(free_func));
         let res = self.llvm.call_func("create_slice", func, &mut vec![]);
+        self.llvm.build_store(res, res_slot);
         let field_ptr = self.llvm.get_struct_field_ptr(res, 0);
         let field_val = self.llvm.load_field_by_ptr(field_ptr);
         self.call_printf_func_by_value("create_array: %d\n", field_val);
@@ -262,9 +1884,511 @@ impl LLVMRunner {
         let ret = self.llvm.void_t();
         let hello_one_type = self.llvm.mk_func_type(ret, &mut argts);
         let name = self.llvm.mk_global_string("name", name);
-        let mut hello_one_args = vec![name];
+        let mut hello_one_args = vec![self.llvm.get_struct_field_ptr(name, 0)];
         let hello_one_func = self.llvm.mk_func(func_name, hello_one_type);
-        self.llvm
-            .call_func(func_name, hello_one_func, &mut hello_one_args);
+        self.llvm.call_func("", hello_one_func, &mut hello_one_args);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quickcheck::{Arbitrary, Gen, TestResult};
+    use std::collections::HashMap;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_char;
+    use std::ptr;
+
+    const OPS: [BignumOp; 5] = [
+        BignumOp::Add,
+        BignumOp::Sub,
+        BignumOp::Mul,
+        BignumOp::Div,
+        BignumOp::Rem,
+    ];
+    const RADIXES: [u32; 6] = [2, 8, 10, 16, 36, 64];
+    const EDGES: [i64; 9] = [
+        0,
+        1,
+        -1,
+        1 << 32,
+        -(1 << 62),
+        i64::MAX,
+        i64::MAX - 1,
+        i64::MIN,
+        i64::MIN + 1,
+    ];
+    const NOTATIONS: [(&str, u32); 5] = [("", 10), ("0x", 16), ("0X", 16), ("0o", 8), ("0b", 2)];
+
+    type CalcFn = extern "C" fn(*const c_char, *const c_char, *mut *mut c_char);
+
+    /// Number as generated code reads it: optionally signed and prefixed.
+    #[derive(Debug, Clone)]
+    struct Operand(String);
+
+    impl Operand {
+        fn new(value: &BigInt, notation: (&str, u32)) -> Operand {
+            let (prefix, radix) = notation;
+            let magnitude = if value.is_negative() {
+                -value
+            } else {
+                value.clone()
+            };
+            let digits = magnitude.to_str_radix(radix).unwrap();
+            let sign = if value.is_negative() { "-" } else { "" };
+            Operand(format!("{}{}{}", sign, prefix, digits))
+        }
+
+        fn value(&self) -> BigInt {
+            BigInt::from_str_prefixed(&self.0, 10).unwrap()
+        }
+    }
+
+    impl Arbitrary for Operand {
+        fn arbitrary<G: Gen>(g: &mut G) -> Operand {
+            // a quarter of the operands sits on the i64 boundaries of the fast path
+            let value = if u8::arbitrary(g) % 4 == 0 {
+                BigInt::from_i64(EDGES[usize::arbitrary(g) % EDGES.len()]).unwrap()
+            } else {
+                let len = 1 + usize::arbitrary(g) % 40;
+                let hex: String = (0..len)
+                    .map(|_| b"0123456789abcdef"[usize::arbitrary(g) % 16] as char)
+                    .collect();
+                let num = BigInt::from_str_radix(&hex, 16).unwrap();
+                if bool::arbitrary(g) {
+                    -num
+                } else {
+                    num
+                }
+            };
+            Operand::new(&value, NOTATIONS[usize::arbitrary(g) % NOTATIONS.len()])
+        }
+
+        fn shrink(&self) -> Box<dyn Iterator<Item = Operand>> {
+            let value = self.value();
+            if value.is_zero() {
+                return Box::new(None.into_iter());
+            }
+            let sixteen = BigInt::from_i64(16).unwrap();
+            let candidates = vec![
+                Operand::new(&BigInt::new().unwrap(), NOTATIONS[0]),
+                Operand::new(&(&value / &sixteen), NOTATIONS[0]),
+            ];
+            Box::new(candidates.into_iter())
+        }
+    }
+
+    /// Module of JIT compiled `void f(i8* lhs, i8* rhs, i8** res)` functions
+    /// storing into `res` a `malloc`ed string with the result.
+    struct Calculator {
+        // keep the execution engines alive
+        _runner: LLVMRunner,
+        _unchecked: LLVMRunner,
+        funcs: HashMap<String, usize>,
+    }
+
+    impl Calculator {
+        fn new() -> Calculator {
+            load_runtime_symbols();
+            let mut runner = LLVMRunner::with_backend(ErrorMode::Checked, Backend::default());
+            let mut names = vec![];
+            for &op in OPS.iter() {
+                for &hybrid in [false, true].iter() {
+                    let name = op_func_name(op, hybrid);
+                    mk_calc_func(&mut runner, &name, Some(op), hybrid, 10);
+                    names.push(name);
+                }
+            }
+            for &radix in RADIXES.iter() {
+                let name = format!("format_{}", radix);
+                mk_calc_func(&mut runner, &name, None, true, radix);
+                names.push(name);
+            }
+            assert!(runner.llvm.start_jit());
+            let mut funcs: HashMap<String, usize> = names
+                .into_iter()
+                .map(|name| {
+                    let addr = runner.llvm.func_address(&name).unwrap();
+                    (name, addr as usize)
+                })
+                .collect();
+
+            let mut unchecked = LLVMRunner::with_backend(ErrorMode::Unchecked, Backend::default());
+            mk_parse_func(&mut unchecked, "parse");
+            assert!(unchecked.llvm.start_jit());
+            let addr = unchecked.llvm.func_address("parse").unwrap();
+            funcs.insert("parse".to_owned(), addr as usize);
+            Calculator {
+                _runner: runner,
+                _unchecked: unchecked,
+                funcs,
+            }
+        }
+
+        fn run(&self, name: &str, lhs: &Operand, rhs: &Operand) -> String {
+            self.try_run(name, &lhs.0, &rhs.0)
+                .unwrap_or_else(|| panic!("{} returned no result", name))
+        }
+
+        /// Result of `name`, `None` when it failed.
+        fn try_run(&self, name: &str, lhs: &str, rhs: &str) -> Option<String> {
+            let func: CalcFn = unsafe { mem::transmute(self.funcs[name]) };
+            let lhs = CString::new(lhs).unwrap();
+            let rhs = CString::new(rhs).unwrap();
+            let mut res = ptr::null_mut();
+            func(lhs.as_ptr(), rhs.as_ptr(), &mut res);
+            if res.is_null() {
+                return None;
+            }
+            unsafe {
+                let s = CStr::from_ptr(res).to_string_lossy().into_owned();
+                libc::free(res as *mut libc::c_void);
+                Some(s)
+            }
+        }
+    }
+
+    thread_local! {
+        static CALCULATOR: Calculator = Calculator::new();
+    }
+
+    fn op_func_name(op: BignumOp, hybrid: bool) -> String {
+        let repr = if hybrid { "hybrid" } else { "bignum" };
+        format!("{}_{}", repr, op.name())
+    }
+
+    /// Parses both operands like `mk_main`, applies `op`, or formats `lhs`
+    /// when there is none, and prints the result in `radix`.
+    fn mk_calc_func(
+        runner: &mut LLVMRunner,
+        name: &str,
+        op: Option<BignumOp>,
+        hybrid: bool,
+        radix: u32,
+    ) {
+        let i8_pt = runner.llvm.ptr_t(runner.llvm.i8_t());
+        let i8_ppt = runner.llvm.ptr_t(i8_pt);
+        let mut params = [i8_pt, i8_pt, i8_ppt];
+        runner.mk_void_func(name, &mut params, &mut |r, func| {
+            let lhs_ptr = r.llvm.get_param(func, 0);
+            let rhs_ptr = r.llvm.get_param(func, 1);
+            let res_str = r.llvm.get_param(func, 2);
+            let res = if hybrid {
+                let lhs = r.alloc_hybrid("lhs");
+                let rhs = r.alloc_hybrid("rhs");
+                let res = r.alloc_hybrid("res");
+                r.call_hybrid_parse(lhs, lhs_ptr, InputRadix::Prefixed);
+                r.call_hybrid_parse(rhs, rhs_ptr, InputRadix::Prefixed);
+                match op {
+                    Some(op) => {
+                        r.call_hybrid_op(op, lhs, rhs, res);
+                        r.hybrid_to_bignum(res)
+                    }
+                    None => r.hybrid_to_bignum(lhs),
+                }
+            } else {
+                let lhs = r.alloc_bignum("lhs");
+                let rhs = r.alloc_bignum("rhs");
+                let res = r.alloc_bignum("res");
+                r.call_bignum_parse(lhs, lhs_ptr, InputRadix::Prefixed);
+                r.call_bignum_parse(rhs, rhs_ptr, InputRadix::Prefixed);
+                r.call_bignum_op(
+                    op.expect("bignum functions need an operation"),
+                    lhs,
+                    rhs,
+                    res,
+                );
+                res
+            };
+            let i32_t = r.llvm.i32_t();
+            let size_ref = r.llvm.build_alloca("size", i32_t);
+            r.call_bignum_format_size(res, size_ref, radix);
+            let str_ptr = r.call_malloc(size_ref);
+            r.call_bignum_format(str_ptr, res, res_str, radix);
+        });
+    }
+
+    /// Parses `lhs` like `mk_calc_func` and prints it in decimal, returning
+    /// with `res` left null when it isn't a number. `runner` has to leave
+    /// the status unchecked.
+    fn mk_parse_func(runner: &mut LLVMRunner, name: &str) {
+        let i8_pt = runner.llvm.ptr_t(runner.llvm.i8_t());
+        let i8_ppt = runner.llvm.ptr_t(i8_pt);
+        let mut params = [i8_pt, i8_pt, i8_ppt];
+        runner.mk_void_func(name, &mut params, &mut |r, func| {
+            let lhs_ptr = r.llvm.get_param(func, 0);
+            let res_str = r.llvm.get_param(func, 2);
+            let num = r.alloc_bignum("lhs");
+            let status = r.call_bignum_parse(num, lhs_ptr, InputRadix::Prefixed);
+            let ok = r
+                .llvm
+                .const_int(r.llvm.i32_t(), i64::from(r.bignum.ok_status()));
+            let parsed = r.llvm.build_icmp(LLVMIntPredicate::LLVMIntEQ, status, ok);
+            let format_block = r.llvm.mk_basic_block("format", func);
+            let done_block = r.llvm.mk_basic_block("done", func);
+            r.llvm.build_cond_br(parsed, format_block, done_block);
+            r.llvm.position_at_end(format_block);
+            let i32_t = r.llvm.i32_t();
+            let size_ref = r.llvm.build_alloca("size", i32_t);
+            r.call_bignum_format_size(num, size_ref, 10);
+            let str_ptr = r.call_malloc(size_ref);
+            r.call_bignum_format(str_ptr, num, res_str, 10);
+            r.llvm.build_br(done_block);
+            r.llvm.position_at_end(done_block);
+        });
+    }
+
+    fn check_op(op: BignumOp, hybrid: bool, lhs: &Operand, rhs: &Operand) -> TestResult {
+        let (a, b) = (lhs.value(), rhs.value());
+        let divides = op == BignumOp::Div || op == BignumOp::Rem;
+        if divides && b.is_zero() {
+            // generated code reports it and exits
+            return TestResult::discard();
+        }
+        let expected = match op {
+            BignumOp::Add => &a + &b,
+            BignumOp::Sub => &a - &b,
+            BignumOp::Mul => &a * &b,
+            BignumOp::Div => &a / &b,
+            BignumOp::Rem => &a % &b,
+        };
+        let name = op_func_name(op, hybrid);
+        let actual = CALCULATOR.with(|c| c.run(&name, lhs, rhs));
+        if actual == expected.to_string() {
+            TestResult::passed()
+        } else {
+            TestResult::error(format!(
+                "{}({}, {}): expected {}, got {}",
+                name, lhs.0, rhs.0, expected, actual
+            ))
+        }
+    }
+
+    quickcheck! {
+        fn bignum_add(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Add, false, &lhs, &rhs)
+        }
+
+        fn bignum_sub(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Sub, false, &lhs, &rhs)
+        }
+
+        fn bignum_mul(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Mul, false, &lhs, &rhs)
+        }
+
+        fn bignum_div(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Div, false, &lhs, &rhs)
+        }
+
+        fn bignum_rem(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Rem, false, &lhs, &rhs)
+        }
+
+        fn hybrid_add(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Add, true, &lhs, &rhs)
+        }
+
+        fn hybrid_sub(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Sub, true, &lhs, &rhs)
+        }
+
+        fn hybrid_mul(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Mul, true, &lhs, &rhs)
+        }
+
+        fn hybrid_div(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Div, true, &lhs, &rhs)
+        }
+
+        fn hybrid_rem(lhs: Operand, rhs: Operand) -> TestResult {
+            check_op(BignumOp::Rem, true, &lhs, &rhs)
+        }
+
+        fn parse_like_host(num: Operand, sign_at: usize) -> TestResult {
+            // mostly malformed: "--5", "-0x-1f", "0-x1f", "5-"
+            let mut text = num.0.clone();
+            text.insert(sign_at % (text.len() + 1), '-');
+            let expected = BigInt::from_str_prefixed(&text, 10)
+                .ok()
+                .map(|n| n.to_string());
+            let actual = CALCULATOR.with(|c| c.try_run("parse", &text, &text));
+            if actual == expected {
+                TestResult::passed()
+            } else {
+                TestResult::error(format!(
+                    "parsing {:?}: expected {:?}, got {:?}",
+                    text, expected, actual
+                ))
+            }
+        }
+
+        fn format_in_radix(num: Operand, radix_index: usize) -> TestResult {
+            let radix = RADIXES[radix_index % RADIXES.len()];
+            let expected = num.value().to_str_radix(radix).unwrap();
+            let name = format!("format_{}", radix);
+            let actual = CALCULATOR.with(|c| c.run(&name, &num, &num));
+            if actual == expected {
+                TestResult::passed()
+            } else {
+                TestResult::error(format!(
+                    "{} in radix {}: expected {}, got {}",
+                    num.0, radix, expected, actual
+                ))
+            }
+        }
+    }
+
+    #[test]
+    fn embeds_bignum_constants() {
+        load_runtime_symbols();
+        let one = BigInt::from_i64(1).unwrap();
+        let mersenne = &BigInt::from_i64(2).unwrap().pow(521).unwrap() - &one;
+        let values = [
+            -&mersenne,
+            mersenne,
+            BigInt::new().unwrap(),
+            BigInt::from_i64(i64::MIN).unwrap(),
+            &BigInt::from_i64(i64::MAX).unwrap() + &one,
+        ];
+        let mut runner = LLVMRunner::with_backend(ErrorMode::Checked, Backend::default());
+        let i8_pt = runner.llvm.ptr_t(runner.llvm.i8_t());
+        let mut params = [runner.llvm.ptr_t(i8_pt)];
+        for (i, value) in values.iter().enumerate() {
+            runner.mk_void_func(&format!("const_{}", i), &mut params, &mut |r, func| {
+                let res_str = r.llvm.get_param(func, 0);
+                let num = r.alloc_hybrid("num");
+                r.hybrid_set_const(num, value);
+                let num = r.hybrid_to_bignum(num);
+                let i32_t = r.llvm.i32_t();
+                let size_ref = r.llvm.build_alloca("size", i32_t);
+                r.call_bignum_format_size(num, size_ref, 10);
+                let str_ptr = r.call_malloc(size_ref);
+                r.call_bignum_format(str_ptr, num, res_str, 10);
+            });
+        }
+        assert!(runner.llvm.start_jit());
+        for (i, value) in values.iter().enumerate() {
+            let addr = runner.llvm.func_address(&format!("const_{}", i)).unwrap();
+            let func: extern "C" fn(*mut *mut c_char) = unsafe { mem::transmute(addr) };
+            let mut res = ptr::null_mut();
+            func(&mut res);
+            let printed = unsafe { CStr::from_ptr(res).to_string_lossy().into_owned() };
+            unsafe { libc::free(res as *mut libc::c_void) };
+            assert_eq!(printed, value.to_string());
+        }
+    }
+
+    #[test]
+    fn demotes_after_overflow() {
+        load_runtime_symbols();
+        let mut runner = LLVMRunner::with_backend(ErrorMode::Checked, Backend::default());
+        let i64_t = runner.llvm.i64_t();
+        let i8_pt = runner.llvm.ptr_t(runner.llvm.i8_t());
+        let mut params = [i64_t, i64_t, runner.llvm.ptr_t(i8_pt), i8_pt];
+        let round_trips = [
+            ("add_sub", BignumOp::Add, BignumOp::Sub),
+            ("mul_div", BignumOp::Mul, BignumOp::Div),
+        ];
+        // `(lhs op rhs) inverse rhs`, storing its decimal string into `res`
+        // and whether it is held as a bignum into `big`
+        for &(name, op, inverse) in round_trips.iter() {
+            runner.mk_void_func(name, &mut params, &mut |r, func| {
+                let lhs = r.alloc_hybrid("lhs");
+                let rhs = r.alloc_hybrid("rhs");
+                let tmp = r.alloc_hybrid("tmp");
+                let res = r.alloc_hybrid("res");
+                let lhs_value = r.llvm.get_param(func, 0);
+                r.hybrid_set_i64(lhs, lhs_value);
+                let rhs_value = r.llvm.get_param(func, 1);
+                r.hybrid_set_i64(rhs, rhs_value);
+                r.call_hybrid_op(op, lhs, rhs, tmp);
+                r.call_hybrid_op(inverse, tmp, rhs, res);
+                let is_big = r.hybrid_is_big(res);
+                let i8_t = r.llvm.i8_t();
+                let is_big = r.llvm.zero_extend(is_big, i8_t);
+                let big = r.llvm.get_param(func, 3);
+                r.llvm.build_store(is_big, big);
+                let num = r.hybrid_to_bignum(res);
+                let i32_t = r.llvm.i32_t();
+                let size_ref = r.llvm.build_alloca("size", i32_t);
+                r.call_bignum_format_size(num, size_ref, 10);
+                let str_ptr = r.call_malloc(size_ref);
+                let res_str = r.llvm.get_param(func, 2);
+                r.call_bignum_format(str_ptr, num, res_str, 10);
+            });
+        }
+        assert!(runner.llvm.start_jit());
+        let operands = [
+            (i64::MAX, 1),
+            (i64::MIN, -1),
+            (i64::MAX, i64::MAX),
+            (i64::MIN, i64::MIN),
+            (i64::MIN, i64::MAX),
+            (1 << 62, 3),
+            (-7, 5),
+        ];
+        for &(name, _, _) in round_trips.iter() {
+            let addr = runner.llvm.func_address(name).unwrap();
+            let func: extern "C" fn(i64, i64, *mut *mut c_char, *mut u8) =
+                unsafe { mem::transmute(addr) };
+            for &(lhs, rhs) in operands.iter() {
+                let mut res = ptr::null_mut();
+                let mut big = 1;
+                func(lhs, rhs, &mut res, &mut big);
+                let printed = unsafe { CStr::from_ptr(res).to_string_lossy().into_owned() };
+                unsafe { libc::free(res as *mut libc::c_void) };
+                assert_eq!(printed, lhs.to_string(), "{}({}, {})", name, lhs, rhs);
+                assert_eq!(big, 0, "{}({}, {}) stayed a bignum", name, lhs, rhs);
+            }
+        }
+    }
+
+    #[test]
+    fn repl_recovers_from_failures() {
+        let options = Options::default();
+        let mut repl = Repl::new(&options, &["repl".to_owned()]);
+        assert_eq!(repl.run("let x = 5;\n"), Some(true));
+        assert_eq!(
+            repl.run("fn inv(n: int) -> int {\n    return n / (n - n);\n}\n"),
+            Some(true)
+        );
+        assert_eq!(repl.run("x = inv(x);\n"), Some(false));
+        assert_eq!(repl.run("print y;\n"), Some(false));
+        assert_eq!(repl.run("x = x + 1;\n"), Some(true));
+        // `x` kept its value through the failures
+        assert_eq!(repl.run("if x != 6 { x = x / (x - x); }\n"), Some(true));
+    }
+
+    #[test]
+    fn parses_operand_sources() {
+        assert_eq!(Operands::parse("stdin"), Ok(Operands::Stdin));
+        assert_eq!(Operands::parse("args"), Ok(Operands::Args));
+        assert_eq!(
+            Operands::parse("file:in.txt"),
+            Ok(Operands::File(PathBuf::from("in.txt")))
+        );
+        assert_eq!(
+            Operands::parse("0x10,-3"),
+            Ok(Operands::Given(vec!["0x10".to_owned(), "-3".to_owned()]))
+        );
+        assert!(Operands::parse("1").is_err());
+        assert!(Operands::parse("1,").is_err());
+        assert!(Operands::parse("1,2,3").is_err());
+    }
+
+    #[test]
+    fn lowers_programs_in_memory() {
+        let source = "fn sq(n: bigint) -> bigint { return n * n; }\nprint sq(read);";
+        let program = mir::build(source, &ast::parse(source).unwrap()).unwrap();
+        let mut runner = LLVMRunner::with_backend(ErrorMode::Checked, Backend::default());
+        runner.lower_program(&program);
+        let ir = runner.llvm.ir_string();
+        assert!(ir.contains("define i32 @main(i32"), "{}", ir);
+        assert!(ir.contains("@calc.argv = global i8** null"), "{}", ir);
+        assert!(ir.contains("define void @calc.sq(%hybrid_int*"), "{}", ir);
+        let mut written = vec![];
+        runner.llvm.write_ir(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), ir);
     }
 }
@@ -0,0 +1,454 @@
+use super::*;
+use mir::*;
+use std::collections::HashMap;
+
+/// Function being lowered with the storage of its locals: a hybrid integer
+/// for a `bigint`, a stack slot holding the scalar for the other types.
+struct Frame<'f> {
+    func: &'f Function,
+    slots: Vec<LLVMValueRef>,
+    functions: &'f HashMap<String, LLVMValueRef>,
+}
+
+impl LLVMRunner {
+    /// Generates every function of `program`, returning the first one,
+    /// its `main`. A function named `main` is the C entry point, returning
+    /// the exit status.
+    ///
+    /// `bigint` arguments are passed as pointers to the caller's hybrid
+    /// integers, and a `bigint` is returned by storing it into the hybrid
+    /// integer the caller passes as first argument. Functions called but
+    /// not defined by `program` are declared for another module to define.
+    pub(super) fn lower_program(&mut self, program: &Program) -> LLVMValueRef {
+        let mut functions = HashMap::new();
+        for (i, func) in program.functions.iter().enumerate() {
+            let params = func.param_types();
+            let llvm_func = self.declare_function(&func.name, &params, func.ret_type(), i == 0);
+            functions.insert(func.name.clone(), llvm_func);
+        }
+        for func in &program.functions {
+            for block in &func.blocks {
+                for inst in &block.insts {
+                    if let Inst::Call(dest, ref name, ref args) = *inst {
+                        if functions.contains_key(name) {
+                            continue;
+                        }
+                        let params: Vec<Type> = args.iter().map(|a| func.operand_type(a)).collect();
+                        let ret = dest.map(|dest| func.local_type(dest));
+                        let llvm_func = self.declare_function(name, &params, ret, false);
+                        functions.insert(name.clone(), llvm_func);
+                    }
+                }
+            }
+        }
+        for (i, func) in program.functions.iter().enumerate() {
+            let is_main = i == 0 && func.name == "main";
+            self.define_func(functions[&func.name], &mut |r, llvm_func| {
+                if is_main {
+                    r.store_main_args(llvm_func);
+                }
+                let ret = r.lower_function_body(func, llvm_func, &functions);
+                if !is_main {
+                    return ret;
+                }
+                let i32_t = r.llvm.i32_t();
+                Some(match ret {
+                    Some(status) => r.llvm.build_trunc(status, i32_t),
+                    None => r.llvm.const_int(i32_t, 0),
+                })
+            });
+        }
+        functions[&program.functions[0].name]
+    }
+
+    fn declare_function(
+        &mut self,
+        name: &str,
+        param_types: &[Type],
+        ret_type: Option<Type>,
+        entry: bool,
+    ) -> LLVMValueRef {
+        let mut params = vec![];
+        let ret = match ret_type {
+            Some(Type::BigInt) => {
+                params.push(self.value_type(Type::BigInt));
+                self.llvm.void_t()
+            }
+            Some(ty) => self.value_type(ty),
+            None => self.llvm.void_t(),
+        };
+        for &ty in param_types {
+            params.push(self.value_type(ty));
+        }
+        let func_type = if entry && name == "main" {
+            self.main_func_type()
+        } else {
+            self.llvm.mk_func_type(ret, &mut params)
+        };
+        // keeps clear of the runtime's symbols
+        let name = if entry {
+            name.to_string()
+        } else {
+            format!("calc.{}", name)
+        };
+        self.llvm.mk_func(&name, func_type)
+    }
+
+    /// Type of a value of type `ty` in registers, a pointer for a `bigint`.
+    fn value_type(&self, ty: Type) -> LLVMTypeRef {
+        match ty {
+            Type::BigInt => self.llvm.ptr_t(self.hybrid_type),
+            Type::Int => self.llvm.i64_t(),
+            Type::Bool => self.llvm.i1_t(),
+            Type::Str => self.llvm.ptr_t(self.llvm.i8_t()),
+        }
+    }
+
+    /// Generates the blocks of `func` into `llvm_func`, returning the value
+    /// to return, loaded where the returns end up.
+    fn lower_function_body(
+        &mut self,
+        func: &Function,
+        llvm_func: LLVMValueRef,
+        functions: &HashMap<String, LLVMValueRef>,
+    ) -> Option<LLVMValueRef> {
+        let ret_type = func.ret_type();
+        let first_param = if ret_type == Some(Type::BigInt) { 1 } else { 0 };
+        let mut slots = vec![];
+        for (i, decl) in func.locals.iter().enumerate() {
+            let local = Local(i);
+            let slot = if func.ret == Some(local) && ret_type == Some(Type::BigInt) {
+                self.llvm.get_param(llvm_func, 0)
+            } else if let Some(pos) = func.params.iter().position(|&p| p == local) {
+                let param = self.llvm.get_param(llvm_func, (first_param + pos) as u32);
+                self.param_slot(i, decl, param, func.is_assigned(local))
+            } else {
+                self.alloc_local(i, decl)
+            };
+            slots.push(slot);
+        }
+        let frame = Frame {
+            func,
+            slots,
+            functions,
+        };
+        let blocks: Vec<LLVMBasicBlockRef> = (0..func.blocks.len())
+            .map(|i| self.llvm.mk_basic_block(&format!("bb{}", i), llvm_func))
+            .collect();
+        let exit_block = self.llvm.mk_basic_block("return", llvm_func);
+        self.llvm.build_br(blocks[0]);
+
+        for (block, &llvm_block) in func.blocks.iter().zip(&blocks) {
+            self.llvm.position_at_end(llvm_block);
+            for inst in &block.insts {
+                self.lower_inst(&frame, inst);
+            }
+            match block.term {
+                Terminator::Return => self.llvm.build_br(exit_block),
+                Terminator::Goto(target) => self.llvm.build_br(blocks[target.0]),
+                Terminator::Branch(ref cond, then, els) => {
+                    let cond = self.scalar_operand(&frame, cond);
+                    self.llvm.build_cond_br(cond, blocks[then.0], blocks[els.0]);
+                }
+                Terminator::Unreachable => self.llvm.build_unreachable(),
+            }
+        }
+        self.llvm.position_at_end(exit_block);
+        match (func.ret, ret_type) {
+            (Some(ret), Some(ty)) if ty != Type::BigInt => {
+                Some(self.llvm.build_load(frame.slots[ret.0]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Storage of a local. Globals are zero-initialized, which is the
+    /// hybrid integer 0, and never released.
+    fn alloc_local(&mut self, index: usize, local: &LocalDecl) -> LLVMValueRef {
+        let slot_type = match local.ty {
+            Type::BigInt => self.hybrid_type,
+            ty => self.value_type(ty),
+        };
+        match local.storage {
+            Storage::Global(ref symbol) => self.llvm.mk_global_var(symbol, slot_type, true),
+            Storage::Extern(ref symbol) => self.llvm.mk_global_var(symbol, slot_type, false),
+            Storage::Stack => {
+                let name = local_name(index, local);
+                match local.ty {
+                    Type::BigInt => self.alloc_hybrid(&name),
+                    _ => self.llvm.build_entry_alloca(&name, slot_type),
+                }
+            }
+        }
+    }
+
+    /// Slot of a parameter. A `bigint` one uses the caller's hybrid
+    /// integer unless the function assigns it.
+    fn param_slot(
+        &mut self,
+        index: usize,
+        local: &LocalDecl,
+        param: LLVMValueRef,
+        assigned: bool,
+    ) -> LLVMValueRef {
+        if local.ty == Type::BigInt && !assigned {
+            return param;
+        }
+        let slot = self.alloc_local(index, local);
+        if local.ty == Type::BigInt {
+            self.call_hybrid_copy(param, slot);
+        } else {
+            self.llvm.build_store(param, slot);
+        }
+        slot
+    }
+
+    fn lower_inst(&mut self, frame: &Frame, inst: &Inst) {
+        match *inst {
+            Inst::Assign(local, ref rvalue) => {
+                let slot = frame.slots[local.0];
+                match frame.func.local_type(local) {
+                    Type::BigInt => self.lower_bigint_assign(frame, slot, rvalue),
+                    _ => {
+                        let value = self.lower_scalar_rvalue(frame, rvalue);
+                        self.llvm.build_store(value, slot);
+                    }
+                }
+            }
+            Inst::Call(dest, ref name, ref args) => {
+                let dest_type = dest.map(|dest| frame.func.local_type(dest));
+                let mut values = vec![];
+                if let (Some(dest), Some(Type::BigInt)) = (dest, dest_type) {
+                    values.push(frame.slots[dest.0]);
+                }
+                for arg in args {
+                    let value = match frame.func.operand_type(arg) {
+                        Type::BigInt => self.hybrid_operand(frame, arg),
+                        _ => self.scalar_operand(frame, arg),
+                    };
+                    values.push(value);
+                }
+                let callee = frame.functions[name];
+                match (dest, dest_type) {
+                    (Some(dest), Some(ty)) if ty != Type::BigInt => {
+                        let value = self.llvm.call_func(name, callee, &mut values);
+                        self.llvm.build_store(value, frame.slots[dest.0]);
+                    }
+                    _ => {
+                        self.llvm.call_func("", callee, &mut values);
+                    }
+                }
+                self.check_callee();
+            }
+            Inst::Print(ref operand) => match frame.func.operand_type(operand) {
+                Type::BigInt => {
+                    let hybrid = self.hybrid_operand(frame, operand);
+                    self.call_print_hybrid(hybrid);
+                }
+                Type::Int => {
+                    let value = self.scalar_operand(frame, operand);
+                    let hybrid = self.alloc_hybrid("print_int");
+                    self.hybrid_set_i64(hybrid, value);
+                    self.call_print_hybrid(hybrid);
+                }
+                Type::Bool => {
+                    let value = self.scalar_operand(frame, operand);
+                    let yes = self.str_const("true");
+                    let no = self.str_const("false");
+                    let text = self.llvm.build_select(value, yes, no);
+                    self.call_printf_func_by_value("%s\n", text);
+                }
+                Type::Str => {
+                    let text = self.scalar_operand(frame, operand);
+                    self.call_printf_func_by_value("%s\n", text);
+                }
+            },
+        }
+    }
+
+    /// Stores the value of `rvalue` into the hybrid integer `res`.
+    fn lower_bigint_assign(&mut self, frame: &Frame, res: LLVMValueRef, rvalue: &Rvalue) {
+        match *rvalue {
+            Rvalue::Use(Operand::Const(Const::BigInt(ref value))) => {
+                self.hybrid_set_const(res, value)
+            }
+            Rvalue::Use(ref operand) => {
+                let from = self.hybrid_operand(frame, operand);
+                self.call_hybrid_copy(from, res);
+            }
+            Rvalue::Compare(..) | Rvalue::Not(_) => unreachable!("comparisons produce a bool"),
+            Rvalue::Binary(op, ref lhs, ref rhs) => {
+                let lhs = self.hybrid_operand(frame, lhs);
+                let rhs = self.hybrid_operand(frame, rhs);
+                self.call_hybrid_op(bignum_op(op), lhs, rhs, res);
+            }
+            Rvalue::Neg(ref operand) => {
+                let value = self.hybrid_operand(frame, operand);
+                let zero = self.alloc_hybrid("zero");
+                self.call_hybrid_op(BignumOp::Sub, zero, value, res);
+            }
+            Rvalue::Cast(ref operand) => {
+                let value = self.scalar_operand(frame, operand);
+                self.hybrid_set_i64(res, value);
+            }
+            Rvalue::Read => self.call_read_hybrid(res),
+            Rvalue::Arg(ref index) => {
+                let index = self.scalar_operand(frame, index);
+                self.call_parse_arg(res, index);
+            }
+            Rvalue::Argc => unreachable!("argc is an int"),
+        }
+    }
+
+    /// Value of an `rvalue` of type `int`, `bool` or `string`.
+    fn lower_scalar_rvalue(&mut self, frame: &Frame, rvalue: &Rvalue) -> LLVMValueRef {
+        match *rvalue {
+            Rvalue::Use(ref operand) => self.scalar_operand(frame, operand),
+            Rvalue::Binary(op, ref lhs, ref rhs) => {
+                let lhs = self.scalar_operand(frame, lhs);
+                let rhs = self.scalar_operand(frame, rhs);
+                self.checked_small_op(bignum_op(op), lhs, rhs)
+            }
+            Rvalue::Neg(ref operand) => {
+                let value = self.scalar_operand(frame, operand);
+                let zero = self.llvm.const_int(self.llvm.i64_t(), 0);
+                self.checked_small_op(BignumOp::Sub, zero, value)
+            }
+            Rvalue::Compare(op, ref lhs, ref rhs) => {
+                let pred = int_predicate(op);
+                match frame.func.operand_type(lhs) {
+                    Type::BigInt => {
+                        let lhs = self.hybrid_operand(frame, lhs);
+                        let rhs = self.hybrid_operand(frame, rhs);
+                        self.call_hybrid_cmp(pred, lhs, rhs)
+                    }
+                    _ => {
+                        let lhs = self.scalar_operand(frame, lhs);
+                        let rhs = self.scalar_operand(frame, rhs);
+                        self.llvm.build_icmp(pred, lhs, rhs)
+                    }
+                }
+            }
+            Rvalue::Not(ref operand) => {
+                let value = self.scalar_operand(frame, operand);
+                let yes = self.llvm.const_int(self.llvm.i1_t(), 1);
+                self.llvm.build_binop(LLVMOpcode::LLVMXor, value, yes)
+            }
+            Rvalue::Cast(ref operand) => {
+                let hybrid = self.hybrid_operand(frame, operand);
+                self.hybrid_demote(hybrid);
+                let is_big = self.hybrid_is_big(hybrid);
+                self.check_failed("bigint too large for an int", is_big);
+                let small = self.llvm.get_struct_field_ptr(hybrid, HYBRID_SMALL);
+                self.llvm.build_load(small)
+            }
+            Rvalue::Argc => {
+                let argc = self.load_argc();
+                let i64_t = self.llvm.i64_t();
+                self.llvm.extend_32_to_64(argc, i64_t)
+            }
+            Rvalue::Read => unreachable!("read always produces a bigint"),
+            Rvalue::Arg(_) => unreachable!("arg always produces a bigint"),
+        }
+    }
+
+    /// `op` on `int`s, reporting a division by zero or an overflow as an
+    /// error.
+    fn checked_small_op(
+        &mut self,
+        op: BignumOp,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+    ) -> LLVMValueRef {
+        if op == BignumOp::Div || op == BignumOp::Rem {
+            let zero = self.llvm.const_int(self.llvm.i64_t(), 0);
+            let by_zero = self.llvm.build_icmp(LLVMIntPredicate::LLVMIntEQ, rhs, zero);
+            self.check_failed("int division by zero", by_zero);
+        }
+        let (value, overflow) = self.build_small_op(op, lhs, rhs);
+        self.check_failed(&format!("int {} overflowed", op.name()), overflow);
+        value
+    }
+
+    /// Number of command line arguments as an `i32`.
+    fn load_argc(&mut self) -> LLVMValueRef {
+        let i32_t = self.llvm.i32_t();
+        let argc = self.llvm.get_or_declare_global(ARGC_GLOBAL, i32_t);
+        self.llvm.build_load(argc)
+    }
+
+    /// Parses the command line argument at the `i64` `index` into `hybrid`,
+    /// failing when there is none.
+    pub(super) fn call_parse_arg(&mut self, hybrid: LLVMValueRef, index: LLVMValueRef) {
+        let i64_t = self.llvm.i64_t();
+        let argc = self.load_argc();
+        let argc = self.llvm.extend_32_to_64(argc, i64_t);
+        // unsigned, so that negative indices are out of range too
+        let out_of_range = self
+            .llvm
+            .build_icmp(LLVMIntPredicate::LLVMIntUGE, index, argc);
+        self.check_failed("arg index out of range", out_of_range);
+        let argv_t = self.llvm.ptr_t(self.llvm.ptr_t(self.llvm.i8_t()));
+        let argv = self.llvm.get_or_declare_global(ARGV_GLOBAL, argv_t);
+        let argv = self.llvm.build_load(argv);
+        let arg_ptr = self.llvm.build_offset(argv, index);
+        let arg = self.llvm.build_load(arg_ptr);
+        let radix = self.radix.input;
+        self.call_hybrid_parse(hybrid, arg, radix);
+    }
+
+    /// Hybrid integer holding a `bigint` operand.
+    fn hybrid_operand(&mut self, frame: &Frame, operand: &Operand) -> LLVMValueRef {
+        match *operand {
+            Operand::Local(local) => frame.slots[local.0],
+            Operand::Const(Const::BigInt(ref value)) => self.hybrid_literal(value),
+            Operand::Const(ref c) => panic!("{} used as a bigint", c),
+        }
+    }
+
+    /// Value of an `int`, `bool` or `string` operand.
+    fn scalar_operand(&mut self, frame: &Frame, operand: &Operand) -> LLVMValueRef {
+        match *operand {
+            Operand::Local(local) => self.llvm.build_load(frame.slots[local.0]),
+            Operand::Const(Const::Int(v)) => self.llvm.const_int(self.llvm.i64_t(), v),
+            Operand::Const(Const::Bool(b)) => self.llvm.const_int(self.llvm.i1_t(), b as i64),
+            Operand::Const(Const::Str(ref s)) => self.str_const(s),
+            Operand::Const(Const::BigInt(_)) => panic!("bigint used as a scalar"),
+        }
+    }
+
+    /// Pointer to a global nul terminated copy of `s`.
+    fn str_const(&mut self, s: &str) -> LLVMValueRef {
+        let global = self.llvm.mk_global_string("str", s);
+        self.llvm.get_struct_field_ptr(global, 0)
+    }
+}
+
+fn bignum_op(op: BinOp) -> BignumOp {
+    match op {
+        BinOp::Add => BignumOp::Add,
+        BinOp::Sub => BignumOp::Sub,
+        BinOp::Mul => BignumOp::Mul,
+        BinOp::Div => BignumOp::Div,
+        BinOp::Rem => BignumOp::Rem,
+    }
+}
+
+/// Signed predicate, which also fits the sign of an `mp_cmp` result.
+fn int_predicate(op: CmpOp) -> LLVMIntPredicate {
+    match op {
+        CmpOp::Eq => LLVMIntPredicate::LLVMIntEQ,
+        CmpOp::Ne => LLVMIntPredicate::LLVMIntNE,
+        CmpOp::Lt => LLVMIntPredicate::LLVMIntSLT,
+        CmpOp::Le => LLVMIntPredicate::LLVMIntSLE,
+        CmpOp::Gt => LLVMIntPredicate::LLVMIntSGT,
+        CmpOp::Ge => LLVMIntPredicate::LLVMIntSGE,
+    }
+}
+
+fn local_name(index: usize, local: &LocalDecl) -> String {
+    match local.name {
+        Some(ref name) => name.clone(),
+        None => format!("_{}", index),
+    }
+}
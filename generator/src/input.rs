@@ -0,0 +1,132 @@
+//! Input runtime of the generated code, `runtime/input.c`, reading tokens
+//! and lines of any length into heap buffers.
+
+extern crate llvm_sys;
+
+use self::llvm_sys::support::LLVMAddSymbol;
+use libc;
+use std::ffi::CString;
+use std::io;
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use std::slice;
+
+pub const CALC_INPUT_OK: c_int = 0;
+pub const CALC_INPUT_ERROR: c_int = -101;
+pub const CALC_INPUT_MEM: c_int = -102;
+pub const CALC_INPUT_TOO_LONG: c_int = -103;
+pub const CALC_INPUT_EOF: c_int = -104;
+
+extern "C" {
+    pub fn calc_read_token(
+        file: *mut libc::FILE,
+        max_len: usize,
+        text: *mut *mut c_char,
+        len: *mut usize,
+    ) -> c_int;
+    pub fn calc_read_line(
+        file: *mut libc::FILE,
+        max_len: usize,
+        text: *mut *mut c_char,
+        len: *mut usize,
+    ) -> c_int;
+}
+
+/// Makes the input functions linked into this binary visible to the JIT.
+pub fn register_symbols() {
+    let symbols: [(&str, *mut c_void); 2] = [
+        ("calc_read_token", calc_read_token as *mut c_void),
+        ("calc_read_line", calc_read_line as *mut c_void),
+    ];
+    for &(name, addr) in symbols.iter() {
+        let name = CString::new(name).unwrap();
+        unsafe { LLVMAddSymbol(name.as_ptr(), addr) }
+    }
+}
+
+/// Reads a line of the C standard input, the one generated code reads, so
+/// that what it buffers is seen by both; `None` at the end of the input.
+pub fn read_stdin_line() -> io::Result<Option<String>> {
+    let mut text = ptr::null_mut();
+    let mut len = 0;
+    let status = unsafe { calc_read_line(ptr::null_mut(), usize::MAX, &mut text, &mut len) };
+    match status {
+        CALC_INPUT_OK => {}
+        CALC_INPUT_EOF => return Ok(None),
+        CALC_INPUT_MEM => return Err(io::Error::new(io::ErrorKind::OutOfMemory, "out of memory")),
+        _ => return Err(io::Error::last_os_error()),
+    }
+    let bytes = unsafe { slice::from_raw_parts(text as *const u8, len) }.to_vec();
+    unsafe { libc::free(text as *mut c_void) };
+    String::from_utf8(bytes)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+    use std::ptr;
+
+    fn read_all(
+        input: &str,
+        read: unsafe extern "C" fn(*mut libc::FILE, usize, *mut *mut c_char, *mut usize) -> c_int,
+        max_len: usize,
+    ) -> Vec<Result<String, c_int>> {
+        let mut results = vec![];
+        unsafe {
+            let file = libc::tmpfile();
+            assert!(!file.is_null());
+            libc::fwrite(input.as_ptr() as *const c_void, 1, input.len(), file);
+            libc::rewind(file);
+            loop {
+                let mut text = ptr::null_mut();
+                let mut len = 0;
+                let status = read(file, max_len, &mut text, &mut len);
+                if status == CALC_INPUT_EOF {
+                    break;
+                }
+                if status == CALC_INPUT_OK {
+                    let s = CStr::from_ptr(text).to_str().unwrap().to_owned();
+                    assert_eq!(s.len(), len);
+                    libc::free(text as *mut c_void);
+                    results.push(Ok(s));
+                } else {
+                    assert!(text.is_null());
+                    results.push(Err(status));
+                }
+            }
+            libc::fclose(file);
+        }
+        results
+    }
+
+    #[test]
+    fn reads_tokens_and_lines_of_any_length() {
+        let long = "7".repeat(1000);
+        let input = format!("  12 \n{}\t-3\n\n0x1f", long);
+        assert_eq!(
+            read_all(&input, calc_read_token, 1 << 20),
+            vec![
+                Ok("12".to_owned()),
+                Ok(long.clone()),
+                Ok("-3".to_owned()),
+                Ok("0x1f".to_owned()),
+            ]
+        );
+        assert_eq!(
+            read_all(&input, calc_read_token, 999),
+            vec![
+                Ok("12".to_owned()),
+                Err(CALC_INPUT_TOO_LONG),
+                Ok("-3".to_owned()),
+                Ok("0x1f".to_owned()),
+            ]
+        );
+        assert_eq!(
+            read_all("a b\n\nc", calc_read_line, 100),
+            vec![Ok("a b".to_owned()), Ok(String::new()), Ok("c".to_owned())]
+        );
+    }
+}
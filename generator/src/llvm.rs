@@ -3,17 +3,23 @@ extern crate llvm_sys;
 use self::llvm_sys::core::*;
 use self::llvm_sys::execution_engine::*;
 use self::llvm_sys::prelude::*;
+use self::llvm_sys::support::LLVMAddSymbol;
 use self::llvm_sys::target::*;
 use self::llvm_sys::target_machine::*;
+use self::llvm_sys::transforms::pass_manager_builder::*;
+use self::llvm_sys::LLVMIntPredicate;
+use self::llvm_sys::LLVMLinkage;
+use self::llvm_sys::LLVMOpcode;
+use self::llvm_sys::LLVMTypeKind;
 use llvm::llvm_sys::analysis::LLVMVerifierFailureAction;
 use llvm::llvm_sys::analysis::LLVMVerifyModule;
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
-use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::ptr;
 use std::ptr::null_mut;
@@ -36,49 +42,27 @@ pub struct LLVM {
     pub module: LLVMModuleRef,
     builder: LLVMBuilderRef,
     pub cstr_owner: CStrOwner,
+    engine: Option<LLVMExecutionEngineRef>,
+    /// Whether `module` has been handed over to `engine`.
+    module_in_jit: bool,
+    /// Directory of the files written by `dump` and `mk_object_file`.
+    pub out_dir: PathBuf,
+}
+
+/// What machine code is generated for.
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetInfo {
+    pub triple: String,
+    pub cpu: String,
+    pub features: String,
 }
 
 pub struct LLVMFuncs {
     pub printf: LLVMValueRef,
-    pub scanf: LLVMValueRef,
     pub free: LLVMValueRef,
     pub malloc: LLVMValueRef,
-    pub mp_init: LLVMValueRef,
-    pub mp_read_radix: LLVMValueRef,
-    pub mp_radix_size: LLVMValueRef,
-    pub mp_toradix: LLVMValueRef,
-    pub mp_add: LLVMValueRef,
-}
-
-pub struct LLVMStructs {
-    pub mp_struct: LLVMTypeRef,
-}
-
-impl LLVMStructs {
-    pub fn new(llvm: &mut LLVM) -> Self {
-        LLVMStructs {
-            mp_struct: export_mp_struct(llvm),
-        }
-    }
-}
-
-fn export_mp_struct(llvm: &mut LLVM) -> LLVMTypeRef {
-    unsafe {
-        let ns = LLVMStructCreateNamed(llvm.context, llvm.cstr_owner.new_str_ptr("mp_struct"));
-        LLVMStructSetBody(
-            ns,
-            [
-                llvm.i32_t(),
-                llvm.i32_t(),
-                llvm.i32_t(),
-                llvm.ptr_t(llvm.i64_t()),
-            ]
-            .as_mut_ptr(),
-            4,
-            LLVM_FALSE,
-        );
-        ns
-    }
+    pub exit: LLVMValueRef,
+    pub dprintf: LLVMValueRef,
 }
 
 impl CStrOwner {
@@ -94,9 +78,17 @@ impl CStrOwner {
     }
 }
 
+impl Default for LLVM {
+    fn default() -> Self {
+        LLVM::new()
+    }
+}
+
+// The LLVM references the functions here take are handles made by this LLVM,
+// which they only pass back to it, so they don't need to be `unsafe`.
 impl LLVM {
     pub fn new() -> Self {
-        println!("Initializing LLVM");
+        debug!("initializing LLVM");
         unsafe {
             let context = LLVMContextCreate();
             let mut cstr_owner = CStrOwner::new();
@@ -108,22 +100,44 @@ impl LLVM {
                 module,
                 builder,
                 cstr_owner,
+                engine: None,
+                module_in_jit: false,
+                out_dir: PathBuf::from("./target"),
             }
         }
     }
 
+    /// Continues in a new empty module, the current one being dropped
+    /// unless it has been added to the JIT.
+    pub fn new_module(&mut self, name: &str) {
+        unsafe {
+            if !self.module_in_jit {
+                LLVMDisposeModule(self.module);
+            }
+            self.module =
+                LLVMModuleCreateWithNameInContext(self.cstr_owner.new_str_ptr(name), self.context);
+        }
+        self.module_in_jit = false;
+    }
+
     pub fn void_t(&self) -> LLVMTypeRef {
         unsafe { LLVMVoidTypeInContext(self.context) }
     }
 
+    pub fn i1_t(&self) -> LLVMTypeRef {
+        unsafe { LLVMInt1TypeInContext(self.context) }
+    }
+
     pub fn i8_t(&self) -> LLVMTypeRef {
         unsafe { LLVMInt8TypeInContext(self.context) }
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn arr_t(&self, t: LLVMTypeRef, cnt: ::libc::c_uint) -> LLVMTypeRef {
         unsafe { LLVMArrayType(t, cnt) }
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn ptr_t(&self, t: LLVMTypeRef) -> LLVMTypeRef {
         unsafe { LLVMPointerType(t, 0) }
     }
@@ -136,6 +150,33 @@ impl LLVM {
         unsafe { LLVMInt64TypeInContext(self.context) }
     }
 
+    /// Struct type `name` of the context, given the body `fields` by the
+    /// first module asking for it, so that the modules made later agree on
+    /// the types of what they share.
+    pub fn mk_named_struct(&mut self, name: &str, fields: &mut [LLVMTypeRef]) -> LLVMTypeRef {
+        unsafe {
+            let name = self.cstr_owner.new_str_ptr(name);
+            let existing = LLVMGetTypeByName(self.module, name);
+            if !existing.is_null() {
+                return existing;
+            }
+            let ns = LLVMStructCreateNamed(self.context, name);
+            LLVMStructSetBody(ns, fields.as_mut_ptr(), fields.len() as u32, LLVM_FALSE);
+            ns
+        }
+    }
+
+    pub fn struct_t(&self, fields: &mut [LLVMTypeRef]) -> LLVMTypeRef {
+        unsafe {
+            LLVMStructTypeInContext(
+                self.context,
+                fields.as_mut_ptr(),
+                fields.len() as u32,
+                LLVM_FALSE,
+            )
+        }
+    }
+
     pub fn struct_test(&self) -> LLVMTypeRef {
         unsafe {
             LLVMPointerType(
@@ -164,24 +205,42 @@ impl LLVM {
         }
     }
 
-    pub fn dump(&self, name: &str) {
-        let file_name = format!("./target/{}.ll", name);
-        println!("Dumping LLVM IR to the file: {}", file_name);
-        if Path::new(&file_name).exists() {
-            match fs::remove_file(&file_name) {
-                Err(e) => println!(
-                    "The file '{}' can't be removed because of the error: {}",
-                    file_name, e
-                ),
-                Ok(_) => {
-                    writing_dump(&file_name, self.module);
-                }
-            }
-        } else {
-            writing_dump(&file_name, self.module);
+    /// Path of the file `name` in `out_dir`, which is created if needed.
+    pub fn out_file(&self, name: &str) -> String {
+        if let Err(e) = fs::create_dir_all(&self.out_dir) {
+            warn!(
+                "The directory '{}' can't be created because of the error: {}",
+                self.out_dir.display(),
+                e
+            );
         }
+        self.out_dir.join(name).to_string_lossy().into_owned()
+    }
+
+    /// Textual LLVM IR of the module.
+    pub fn ir_string(&self) -> String {
+        unsafe {
+            let ir = LLVMPrintModuleToString(self.module);
+            let text = CStr::from_ptr(ir).to_string_lossy().into_owned();
+            LLVMDisposeMessage(ir);
+            text
+        }
+    }
+
+    /// Writes the textual LLVM IR of the module to `out`.
+    pub fn write_ir(&self, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(self.ir_string().as_bytes())
     }
 
+    /// Writes the IR to `<out_dir>/<name>.ll`, returning the path written.
+    pub fn dump(&self, name: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.out_dir)?;
+        let path = self.out_dir.join(format!("{}.ll", name));
+        self.write_ir(&mut File::create(&path)?)?;
+        Ok(path)
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn get_struct_field_ptr(&mut self, struct_ref: LLVMValueRef, index: u32) -> LLVMValueRef {
         unsafe {
             LLVMBuildStructGEP(
@@ -192,6 +251,7 @@ impl LLVM {
             )
         }
     }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn load_field_by_ptr(&mut self, field_ptr: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             LLVMBuildLoad(
@@ -202,6 +262,7 @@ impl LLVM {
         }
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn extend_32_to_64(&mut self, value: LLVMValueRef, dest_type: LLVMTypeRef) -> LLVMValueRef {
         unsafe {
             LLVMBuildSExt(
@@ -213,10 +274,143 @@ impl LLVM {
         }
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_trunc(&mut self, value: LLVMValueRef, dest_type: LLVMTypeRef) -> LLVMValueRef {
+        unsafe {
+            LLVMBuildTrunc(
+                self.builder,
+                value,
+                dest_type,
+                self.cstr_owner.new_str_ptr("trunc"),
+            )
+        }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn zero_extend(&mut self, value: LLVMValueRef, dest_type: LLVMTypeRef) -> LLVMValueRef {
+        unsafe {
+            LLVMBuildZExt(
+                self.builder,
+                value,
+                dest_type,
+                self.cstr_owner.new_str_ptr("zext"),
+            )
+        }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_or(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
+        unsafe { LLVMBuildOr(self.builder, lhs, rhs, self.cstr_owner.new_str_ptr("or")) }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_and(&mut self, lhs: LLVMValueRef, rhs: LLVMValueRef) -> LLVMValueRef {
+        unsafe { LLVMBuildAnd(self.builder, lhs, rhs, self.cstr_owner.new_str_ptr("and")) }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_binop(
+        &mut self,
+        op: LLVMOpcode,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+    ) -> LLVMValueRef {
+        unsafe {
+            LLVMBuildBinOp(
+                self.builder,
+                op,
+                lhs,
+                rhs,
+                self.cstr_owner.new_str_ptr("binop"),
+            )
+        }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_extract_value(&mut self, aggregate: LLVMValueRef, index: u32) -> LLVMValueRef {
+        unsafe {
+            LLVMBuildExtractValue(
+                self.builder,
+                aggregate,
+                index,
+                self.cstr_owner.new_str_ptr("extracted"),
+            )
+        }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_select(
+        &mut self,
+        cond: LLVMValueRef,
+        then_value: LLVMValueRef,
+        else_value: LLVMValueRef,
+    ) -> LLVMValueRef {
+        unsafe {
+            LLVMBuildSelect(
+                self.builder,
+                cond,
+                then_value,
+                else_value,
+                self.cstr_owner.new_str_ptr("select"),
+            )
+        }
+    }
+
+    /// Pointer `offset` elements past `ptr`.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_offset(&mut self, ptr: LLVMValueRef, offset: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            LLVMBuildInBoundsGEP(
+                self.builder,
+                ptr,
+                [offset].as_mut_ptr(),
+                1,
+                self.cstr_owner.new_str_ptr("offset"),
+            )
+        }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn const_int(&self, type_ref: LLVMTypeRef, v: i64) -> LLVMValueRef {
+        unsafe { LLVMConstInt(type_ref, v as u64, LLVM_TRUE) }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn build_alloca(&mut self, name: &str, type_ref: LLVMTypeRef) -> LLVMValueRef {
         unsafe { LLVMBuildAlloca(self.builder, type_ref, self.cstr_owner.new_str_ptr(name)) }
     }
 
+    /// Builds a zero-initialized alloca at the top of the entry block of the
+    /// current function, keeping the builder where it was.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_entry_alloca(&mut self, name: &str, type_ref: LLVMTypeRef) -> LLVMValueRef {
+        unsafe {
+            let current = LLVMGetInsertBlock(self.builder);
+            let entry = LLVMGetEntryBasicBlock(LLVMGetBasicBlockParent(current));
+            let first = LLVMGetFirstInstruction(entry);
+            if first.is_null() {
+                LLVMPositionBuilderAtEnd(self.builder, entry);
+            } else {
+                LLVMPositionBuilderBefore(self.builder, first);
+            }
+            let alloca = LLVMBuildAlloca(self.builder, type_ref, self.cstr_owner.new_str_ptr(name));
+            LLVMBuildStore(self.builder, LLVMConstNull(type_ref), alloca);
+            LLVMPositionBuilderAtEnd(self.builder, current);
+            alloca
+        }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn const_null(&self, type_ref: LLVMTypeRef) -> LLVMValueRef {
+        unsafe { LLVMConstNull(type_ref) }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn element_type(&self, ptr: LLVMValueRef) -> LLVMTypeRef {
+        unsafe { LLVMGetElementType(LLVMTypeOf(ptr)) }
+    }
+
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn build_load(&mut self, struct_ref: LLVMValueRef) -> LLVMValueRef {
         unsafe {
             LLVMBuildLoad(
@@ -227,10 +421,12 @@ impl LLVM {
         }
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn build_store(&mut self, value: LLVMValueRef, ptr: LLVMValueRef) -> LLVMValueRef {
         unsafe { LLVMBuildStore(self.builder, value, ptr) }
     }
 
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn mk_func(&mut self, name: &str, function_type: LLVMTypeRef) -> LLVMValueRef {
         unsafe {
             LLVMAddFunction(
@@ -240,15 +436,91 @@ impl LLVM {
             )
         }
     }
+    /// Function `name` of the module, declared with `function_type` on first use.
+    /// Needed for intrinsics, which must not be declared twice.
+    pub fn get_or_mk_func(&mut self, name: &str, function_type: LLVMTypeRef) -> LLVMValueRef {
+        let func = unsafe { LLVMGetNamedFunction(self.module, self.cstr_owner.new_str_ptr(name)) };
+        if func.is_null() {
+            self.mk_func(name, function_type)
+        } else {
+            func
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn get_param(&self, func: LLVMValueRef, index: u32) -> LLVMValueRef {
+        unsafe { LLVMGetParam(func, index) }
+    }
     pub fn mk_global_string(&mut self, name: &str, value: &str) -> LLVMValueRef {
         unsafe {
             LLVMBuildGlobalString(
                 self.builder,
                 self.cstr_owner.new_str_ptr(value),
-                (&mut self.cstr_owner).new_str_ptr(name),
+                self.cstr_owner.new_str_ptr(name),
             )
         }
     }
+    /// Global variable `name` of type `type_ref`, zero-initialized when
+    /// `defined`, declared for another module to define otherwise.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn mk_global_var(
+        &mut self,
+        name: &str,
+        type_ref: LLVMTypeRef,
+        defined: bool,
+    ) -> LLVMValueRef {
+        unsafe {
+            let global = LLVMAddGlobal(self.module, type_ref, self.cstr_owner.new_str_ptr(name));
+            if defined {
+                LLVMSetInitializer(global, LLVMConstNull(type_ref));
+            }
+            global
+        }
+    }
+    /// Global `name` of the module, declared with `type_ref` on first use.
+    pub fn get_or_declare_global(&mut self, name: &str, type_ref: LLVMTypeRef) -> LLVMValueRef {
+        let global = unsafe { LLVMGetNamedGlobal(self.module, self.cstr_owner.new_str_ptr(name)) };
+        if global.is_null() {
+            self.mk_global_var(name, type_ref, false)
+        } else {
+            global
+        }
+    }
+    /// Private read-only global initialized with `value`.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn mk_global_const(&mut self, name: &str, value: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            let global = LLVMAddGlobal(
+                self.module,
+                LLVMTypeOf(value),
+                self.cstr_owner.new_str_ptr(name),
+            );
+            LLVMSetInitializer(global, value);
+            LLVMSetGlobalConstant(global, LLVM_TRUE);
+            LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+            global
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn const_array(&self, elem_type: LLVMTypeRef, values: &mut [LLVMValueRef]) -> LLVMValueRef {
+        unsafe { LLVMConstArray(elem_type, values.as_mut_ptr(), values.len() as u32) }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn const_named_struct(
+        &self,
+        type_ref: LLVMTypeRef,
+        values: &mut [LLVMValueRef],
+    ) -> LLVMValueRef {
+        unsafe { LLVMConstNamedStruct(type_ref, values.as_mut_ptr(), values.len() as u32) }
+    }
+    /// Constant pointer to the first element of the global array `array`.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn const_first_elem_ptr(&self, array: LLVMValueRef) -> LLVMValueRef {
+        unsafe {
+            let zero = LLVMConstInt(self.i32_t(), 0, LLVM_FALSE);
+            LLVMConstInBoundsGEP(array, [zero, zero].as_mut_ptr(), 2)
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn mk_func_type(
         &mut self,
         return_type: LLVMTypeRef,
@@ -262,6 +534,7 @@ impl LLVM {
             LLVMFunctionType(return_type, param_types, args_type.len() as u32, LLVM_FALSE)
         }
     }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn mk_func_type_varargs(
         &mut self,
         return_type: LLVMTypeRef,
@@ -275,6 +548,7 @@ impl LLVM {
             LLVMFunctionType(return_type, param_types, args_type.len() as u32, LLVM_TRUE)
         }
     }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn call_func(
         &mut self,
         name: &str,
@@ -295,6 +569,7 @@ impl LLVM {
             )
         }
     }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn append_basic_block(&mut self, name: &str, function: LLVMValueRef) {
         unsafe {
             let block = LLVMAppendBasicBlockInContext(
@@ -305,74 +580,288 @@ impl LLVM {
             LLVMPositionBuilderAtEnd(self.builder, block);
         }
     }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn mk_basic_block(&mut self, name: &str, function: LLVMValueRef) -> LLVMBasicBlockRef {
+        unsafe {
+            LLVMAppendBasicBlockInContext(self.context, function, self.cstr_owner.new_str_ptr(name))
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn position_at_end(&mut self, block: LLVMBasicBlockRef) {
+        unsafe { LLVMPositionBuilderAtEnd(self.builder, block) }
+    }
+    pub fn current_block(&self) -> LLVMBasicBlockRef {
+        unsafe { LLVMGetInsertBlock(self.builder) }
+    }
+    pub fn current_func(&self) -> LLVMValueRef {
+        unsafe { LLVMGetBasicBlockParent(self.current_block()) }
+    }
+    /// Return type of `func`, `None` when it returns `void`.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn return_type(&self, func: LLVMValueRef) -> Option<LLVMTypeRef> {
+        unsafe {
+            let ret = LLVMGetReturnType(LLVMGetElementType(LLVMTypeOf(func)));
+            match LLVMGetTypeKind(ret) {
+                LLVMTypeKind::LLVMVoidTypeKind => None,
+                _ => Some(ret),
+            }
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_icmp(
+        &mut self,
+        op: LLVMIntPredicate,
+        lhs: LLVMValueRef,
+        rhs: LLVMValueRef,
+    ) -> LLVMValueRef {
+        unsafe {
+            LLVMBuildICmp(
+                self.builder,
+                op,
+                lhs,
+                rhs,
+                self.cstr_owner.new_str_ptr("cmp"),
+            )
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_br(&mut self, dest: LLVMBasicBlockRef) {
+        unsafe {
+            LLVMBuildBr(self.builder, dest);
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_cond_br(
+        &mut self,
+        cond: LLVMValueRef,
+        then_block: LLVMBasicBlockRef,
+        else_block: LLVMBasicBlockRef,
+    ) {
+        unsafe {
+            LLVMBuildCondBr(self.builder, cond, then_block, else_block);
+        }
+    }
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn build_phi(
+        &mut self,
+        name: &str,
+        type_ref: LLVMTypeRef,
+        incoming: &mut [(LLVMValueRef, LLVMBasicBlockRef)],
+    ) -> LLVMValueRef {
+        unsafe {
+            let phi = LLVMBuildPhi(self.builder, type_ref, self.cstr_owner.new_str_ptr(name));
+            let mut values: Vec<LLVMValueRef> = incoming.iter().map(|i| i.0).collect();
+            let mut blocks: Vec<LLVMBasicBlockRef> = incoming.iter().map(|i| i.1).collect();
+            LLVMAddIncoming(
+                phi,
+                values.as_mut_ptr(),
+                blocks.as_mut_ptr(),
+                incoming.len() as u32,
+            );
+            phi
+        }
+    }
+    pub fn build_unreachable(&mut self) {
+        unsafe {
+            LLVMBuildUnreachable(self.builder);
+        }
+    }
     pub fn ret_void(&mut self) {
         unsafe {
             LLVMBuildRetVoid(self.builder);
         }
     }
-    pub fn mk_object_file(&mut self, name: &str) -> bool {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn ret(&mut self, value: LLVMValueRef) {
+        unsafe {
+            LLVMBuildRet(self.builder, value);
+        }
+    }
+    /// Runs the standard module optimizations of `opt_level`, 0 to 3, on the
+    /// module; 0 leaves it as is.
+    pub fn optimize(&mut self, opt_level: u32) {
+        if opt_level == 0 {
+            return;
+        }
         unsafe {
-            println!("initializing LLVM to generate object file\n");
+            let builder = LLVMPassManagerBuilderCreate();
+            LLVMPassManagerBuilderSetOptLevel(builder, opt_level);
+            let passes = LLVMCreatePassManager();
+            LLVMPassManagerBuilderPopulateModulePassManager(builder, passes);
+            LLVMRunPassManager(passes, self.module);
+            LLVMDisposePassManager(passes);
+            LLVMPassManagerBuilderDispose(builder);
+        }
+    }
+
+    /// Writes the object file `<out_dir>/<name>.o` for `triple`, the host
+    /// when `None`.
+    pub fn mk_object_file(&mut self, name: &str, triple: Option<&str>, opt_level: u32) -> bool {
+        let file_name = self.out_file(&format!("{}.o", name));
+        self.emit_machine_code(
+            &file_name,
+            LLVMCodeGenFileType::LLVMObjectFile,
+            triple,
+            opt_level,
+        )
+    }
+
+    /// Writes the assembly file `<out_dir>/<name>.s`, see `mk_object_file`.
+    pub fn mk_asm_file(&mut self, name: &str, triple: Option<&str>, opt_level: u32) -> bool {
+        let file_name = self.out_file(&format!("{}.s", name));
+        self.emit_machine_code(
+            &file_name,
+            LLVMCodeGenFileType::LLVMAssemblyFile,
+            triple,
+            opt_level,
+        )
+    }
+
+    /// Triple, CPU and features code is generated for with `triple`, the
+    /// host when `None`.
+    pub fn target_info(&mut self, triple: Option<&str>) -> TargetInfo {
+        let (triple, cpu, features) = self.target_spec(triple);
+        TargetInfo {
+            triple: from_c(triple),
+            cpu: from_c(cpu),
+            features: from_c(features),
+        }
+    }
+
+    fn target_spec(&mut self, triple: Option<&str>) -> (*mut i8, *mut i8, *mut i8) {
+        // the host's CPU and features only apply to its own triple
+        match triple {
+            Some(triple) => (
+                self.cstr_owner.new_str_ptr(triple),
+                self.cstr_owner.new_str_ptr("generic"),
+                self.cstr_owner.new_str_ptr(""),
+            ),
+            None => unsafe {
+                (
+                    LLVMGetDefaultTargetTriple(),
+                    LLVMGetHostCPUName(),
+                    LLVMGetHostCPUFeatures(),
+                )
+            },
+        }
+    }
+
+    /// Checks that the module is well formed, giving LLVM's explanation
+    /// when it isn't.
+    pub fn verify(&self) -> Result<(), String> {
+        unsafe {
+            let mut message = null_mut();
+            let invalid = LLVMVerifyModule(
+                self.module,
+                LLVMVerifierFailureAction::LLVMReturnStatusAction,
+                &mut message,
+            );
+            let text = if message.is_null() {
+                String::new()
+            } else {
+                let text = from_c(message);
+                LLVMDisposeMessage(message);
+                text
+            };
+            if invalid == LLVM_TRUE {
+                Err(text)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Number of functions defined in the module and of their instructions.
+    pub fn code_size(&self) -> (usize, usize) {
+        let (mut functions, mut instructions) = (0, 0);
+        unsafe {
+            let mut func = LLVMGetFirstFunction(self.module);
+            while !func.is_null() {
+                if LLVMIsDeclaration(func) == LLVM_FALSE {
+                    functions += 1;
+                    let mut block = LLVMGetFirstBasicBlock(func);
+                    while !block.is_null() {
+                        let mut inst = LLVMGetFirstInstruction(block);
+                        while !inst.is_null() {
+                            instructions += 1;
+                            inst = LLVMGetNextInstruction(inst);
+                        }
+                        block = LLVMGetNextBasicBlock(block);
+                    }
+                }
+                func = LLVMGetNextFunction(func);
+            }
+        }
+        (functions, instructions)
+    }
+
+    fn emit_machine_code(
+        &mut self,
+        file_name: &str,
+        file_type: LLVMCodeGenFileType,
+        triple: Option<&str>,
+        opt_level: u32,
+    ) -> bool {
+        unsafe {
+            debug!("initializing LLVM to generate {}", file_name);
             LLVM_InitializeAllTargetInfos();
             LLVM_InitializeAllTargets();
             LLVM_InitializeAllTargetMCs();
             LLVM_InitializeAllAsmParsers();
             LLVM_InitializeAllAsmPrinters();
 
-            let mut module_verification_error = empty_mut_c_str!("");
-            LLVMVerifyModule(
-                self.module,
-                LLVMVerifierFailureAction::LLVMPrintMessageAction,
-                &mut module_verification_error,
-            );
+            if let Err(e) = self.verify() {
+                error!("invalid module: {}", e);
+                return false;
+            }
 
-            let triple = LLVMGetDefaultTargetTriple();
-            println!("Triple: {:?}", from_c(triple));
-            let cpu = LLVMGetHostCPUName();
-            println!("CPU: {:?}", from_c(cpu));
-            let features = LLVMGetHostCPUFeatures();
-            println!("Features: {:?}", from_c(features));
+            let (triple, cpu, features) = self.target_spec(triple);
+            info!("Triple: {:?}", from_c(triple));
+            info!("CPU: {:?}", from_c(cpu));
+            info!("Features: {:?}", from_c(features));
 
             LLVMSetTarget(self.module, triple);
 
             let mut target = LLVMGetFirstTarget();
-            println!("{:?}", from_c(LLVMGetTargetName(target)));
 
             let mut getting_target_error = empty_mut_c_str!("");
 
             if LLVMGetTargetFromTriple(triple, &mut target, &mut getting_target_error) == 1 {
-                panic!("can't get target");
+                error!("can't get target: {}", from_c(getting_target_error));
+                return false;
             }
 
             let getting_target_err_str = from_c(getting_target_error);
 
             if !getting_target_err_str.is_empty() {
-                println!("Error getting target: {}", getting_target_err_str);
+                warn!("Error getting target: {}", getting_target_err_str);
             }
-            println!("creating target machine");
+            debug!(
+                "creating target machine for {:?}",
+                from_c(LLVMGetTargetName(target))
+            );
             let target_machine = LLVMCreateTargetMachine(
                 target,
                 triple,
                 cpu,
                 features,
-                LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
-                LLVMRelocMode::LLVMRelocDefault,
+                codegen_opt_level(opt_level),
+                LLVMRelocMode::LLVMRelocPIC,
                 LLVMCodeModel::LLVMCodeModelDefault,
             );
 
             LLVMCreateTargetDataLayout(target_machine);
 
-            let file_name = format!("./target/{}.o\0", name).as_ptr() as *mut i8;
-            println!("file name = {}", from_c(file_name));
-
+            let file_name_owner = CString::new(file_name).unwrap();
+            let file_name = file_name_owner.as_ptr() as *mut i8;
             let mut error_emitting_obj = empty_mut_c_str!("");
-            println!("creating object file");
+            info!("Writing the file: {}", from_c(file_name));
 
             LLVMTargetMachineEmitToFile(
                 target_machine,
                 self.module,
                 file_name,
-                LLVMCodeGenFileType::LLVMObjectFile,
+                file_type,
                 &mut error_emitting_obj,
             );
 
@@ -381,16 +870,22 @@ impl LLVM {
             LLVMDisposeTargetMachine(target_machine);
 
             if !emitting_obj_err_str.is_empty() {
-                println!("ERROR generating file: {}", emitting_obj_err_str);
+                error!("can't generate file: {}", emitting_obj_err_str);
                 false
             } else {
                 true
             }
         }
     }
-    pub fn exec_func(&mut self, func: LLVMValueRef) -> bool {
+    /// Hands the module over to an MCJIT execution engine. Functions can be
+    /// run or looked up afterwards; more code goes into a `new_module`
+    /// added with `add_to_jit`.
+    pub fn start_jit(&mut self) -> bool {
+        if self.engine.is_some() {
+            return true;
+        }
         unsafe {
-            let mut ee = mem::uninitialized();
+            let mut ee = null_mut();
             LLVMLinkInMCJIT();
             LLVM_InitializeNativeTarget();
             LLVM_InitializeNativeAsmPrinter();
@@ -408,85 +903,82 @@ impl LLVM {
             let emitting_obj_err_str = from_c(getting_target_error);
 
             if !emitting_obj_err_str.is_empty() {
-                println!("ERROR generating file: {}", emitting_obj_err_str);
+                error!("can't start the JIT: {}", emitting_obj_err_str);
                 false
             } else {
-                println!("running main");
-                self.dump("output");
-                LLVMRunFunction(ee, func, 0, null_mut());
-                // LLVMRunFunctionAsMain(ee, main, 0, null_mut(), null_mut());
+                self.engine = Some(ee);
+                self.module_in_jit = true;
                 true
             }
         }
     }
+    /// Adds the current module to the JIT, which is started if needed.
+    /// Symbols the module leaves undefined may be defined by the modules
+    /// added before it.
+    pub fn add_to_jit(&mut self) -> bool {
+        let ee = match self.engine {
+            Some(ee) => ee,
+            None => return self.start_jit(),
+        };
+        if !self.module_in_jit {
+            unsafe { LLVMAddModule(ee, self.module) };
+            self.module_in_jit = true;
+        }
+        true
+    }
+    /// Makes `addr` the address of the symbol `name` for JIT compiled code.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn add_symbol(name: &str, addr: *mut ::libc::c_void) {
+        let name = CString::new(name).unwrap();
+        // LLVM keeps its own copy of the name
+        unsafe { LLVMAddSymbol(name.as_ptr(), addr) };
+    }
+    /// Address of the JIT compiled function `name`, see `start_jit`.
+    pub fn func_address(&mut self, name: &str) -> Option<u64> {
+        let ee = self.engine?;
+        let addr = unsafe { LLVMGetFunctionAddress(ee, self.cstr_owner.new_str_ptr(name)) };
+        if addr == 0 {
+            None
+        } else {
+            Some(addr)
+        }
+    }
+    /// Runs the C entry point `main` with the command line `args`, the
+    /// program's name first, returning its exit status. `None` when the
+    /// JIT can't be started.
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    pub fn run_main(&mut self, main: LLVMValueRef, args: &[String]) -> Option<i32> {
+        if !self.start_jit() {
+            return None;
+        }
+        let ee = self.engine?;
+        let args: Vec<CString> = args
+            .iter()
+            .map(|arg| CString::new(arg.as_str()).unwrap())
+            .collect();
+        let argv: Vec<*const i8> = args.iter().map(|arg| arg.as_ptr()).collect();
+        let envp = [ptr::null()];
+        debug!("running main with {:?}", args);
+        let status = unsafe {
+            LLVMRunFunctionAsMain(ee, main, argv.len() as u32, argv.as_ptr(), envp.as_ptr())
+        };
+        debug!("main exited with {}", status);
+        Some(status)
+    }
 }
 
 impl LLVMFuncs {
-    pub fn new(llvm: &mut LLVM, llvm_structs: &LLVMStructs) -> Self {
+    pub fn new(llvm: &mut LLVM) -> Self {
         LLVMFuncs {
             printf: export_printf_func(llvm),
-            scanf: export_scanf_func(llvm),
             free: export_free_func(llvm),
             malloc: export_malloc_func(llvm),
-            mp_init: export_mp_init_func(llvm, llvm_structs),
-            mp_read_radix: export_mp_read_radix(llvm, llvm_structs),
-            mp_radix_size: export_mp_radix_size(llvm, llvm_structs),
-            mp_toradix: export_mp_to_radix(llvm, llvm_structs),
-            mp_add: export_mp_add(llvm, llvm_structs),
+            exit: export_exit_func(llvm),
+            dprintf: export_dprintf_func(llvm),
         }
     }
 }
 
-pub fn export_mp_add(llvm: &mut LLVM, llvm_structs: &LLVMStructs) -> LLVMValueRef {
-    let mp_s_p = llvm.ptr_t(llvm_structs.mp_struct);
-    let mut args = [mp_s_p, mp_s_p, mp_s_p];
-    let ret = llvm.i32_t();
-    let func_type = llvm.mk_func_type(ret, &mut args);
-    llvm.mk_func("mp_add", func_type)
-}
-
-pub fn export_mp_to_radix(llvm: &mut LLVM, llvm_structs: &LLVMStructs) -> LLVMValueRef {
-    let mut args = [
-        llvm.ptr_t(llvm_structs.mp_struct),
-        llvm.ptr_t(llvm.i8_t()),
-        llvm.i32_t(),
-    ];
-    let ret = llvm.i32_t();
-    let mp_toradix_type = llvm.mk_func_type(ret, &mut args);
-    llvm.mk_func("mp_toradix", mp_toradix_type)
-}
-
-pub fn export_mp_radix_size(llvm: &mut LLVM, llvm_structs: &LLVMStructs) -> LLVMValueRef {
-    let mut args = [
-        llvm.ptr_t(llvm_structs.mp_struct),
-        llvm.i32_t(),
-        llvm.ptr_t(llvm.i32_t()),
-    ];
-    let ret = llvm.i32_t();
-    let mp_read_radix = llvm.mk_func_type(ret, &mut args);
-    llvm.mk_func("mp_radix_size", mp_read_radix)
-}
-
-pub fn export_mp_read_radix(llvm: &mut LLVM, llvm_structs: &LLVMStructs) -> LLVMValueRef {
-    let func_name2 = "mp_read_radix";
-    let mut args = [
-        llvm.ptr_t(llvm_structs.mp_struct),
-        llvm.ptr_t(llvm.i8_t()),
-        llvm.i32_t(),
-    ];
-    let ret = llvm.i32_t();
-    let mp_read_radix = llvm.mk_func_type(ret, &mut args);
-    llvm.mk_func(func_name2, mp_read_radix)
-}
-
-pub fn export_mp_init_func(llvm: &mut LLVM, llvm_structs: &LLVMStructs) -> LLVMValueRef {
-    let func_name = "mp_init";
-    let ret = llvm.i32_t();
-    let mp_sp = llvm.ptr_t(llvm_structs.mp_struct);
-    let create_bigint = llvm.mk_func_type(ret, &mut [mp_sp]);
-    llvm.mk_func(func_name, create_bigint)
-}
-
 pub fn gen_const(llvm: &mut LLVM, v: u64) -> LLVMValueRef {
     unsafe { LLVMConstInt(llvm.i32_t(), v, 0) }
 }
@@ -498,13 +990,6 @@ fn export_printf_func(llvm: &mut LLVM) -> LLVMValueRef {
     llvm.mk_func("printf", printf_type)
 }
 
-fn export_scanf_func(llvm: &mut LLVM) -> LLVMValueRef {
-    let mut argts = [llvm.ptr_t(llvm.i8_t())];
-    let ret = llvm.i32_t();
-    let printf_type = llvm.mk_func_type_varargs(ret, &mut argts);
-    llvm.mk_func("scanf", printf_type)
-}
-
 fn export_free_func(llvm: &mut LLVM) -> LLVMValueRef {
     let mut argts = [llvm.ptr_t(llvm.i8_t())];
     let ret = llvm.void_t();
@@ -519,12 +1004,32 @@ fn export_malloc_func(llvm: &mut LLVM) -> LLVMValueRef {
     llvm.mk_func("malloc", func_type)
 }
 
+fn export_exit_func(llvm: &mut LLVM) -> LLVMValueRef {
+    let mut argts = [llvm.i32_t()];
+    let ret = llvm.void_t();
+    let func_type = llvm.mk_func_type(ret, &mut argts);
+    llvm.mk_func("exit", func_type)
+}
+
+fn export_dprintf_func(llvm: &mut LLVM) -> LLVMValueRef {
+    let mut argts = [llvm.i32_t(), llvm.ptr_t(llvm.i8_t())];
+    let ret = llvm.i32_t();
+    let func_type = llvm.mk_func_type_varargs(ret, &mut argts);
+    llvm.mk_func("dprintf", func_type)
+}
+
 impl Drop for LLVM {
     fn drop(&mut self) {
-        println!("shutting down LLVM...");
+        debug!("shutting down LLVM");
         unsafe {
             LLVMDisposeBuilder(self.builder);
-            LLVMDisposeModule(self.module);
+            // the engine owns the modules added to it
+            if !self.module_in_jit {
+                LLVMDisposeModule(self.module);
+            }
+            if let Some(ee) = self.engine {
+                LLVMDisposeExecutionEngine(ee);
+            }
             LLVMContextDispose(self.context);
         }
     }
@@ -536,49 +1041,114 @@ fn from_c(c_str: *const i8) -> String {
     s.to_owned()
 }
 
-#[cfg(target_os = "macos")]
-fn lib_ext() -> String {
-    "dylib".to_owned()
+#[cfg(not(feature = "rust-bignum"))]
+fn bignum_libs() -> Result<Vec<String>, String> {
+    Ok(vec![
+        concat!(env!("TOMMATH_LIB_DIR"), "/libtommath.a").to_owned()
+    ])
 }
 
-pub fn link() -> bool {
-    let cc = Command::new("cc")
-        .arg("./target/output.o")
-        .arg(format!(
-            "../test-lib/target/debug/libtest_lib.{}",
-            lib_ext()
-        ))
-        .arg("/Users/jinnzest/Documents/nulljinn/tests/libtommath/libtommath.a")
-        .arg("-o")
-        .arg("./target/out")
-        .output()
-        .expect("");
-    println!("status: {}", cc.status);
-    println!("stdout: {}", String::from_utf8_lossy(&cc.stdout));
-    println!("stderr: {}", String::from_utf8_lossy(&cc.stderr));
-    if cc.stderr.is_empty() {
-        true
-    } else {
-        false
+#[cfg(feature = "rust-bignum")]
+fn bignum_libs() -> Result<Vec<String>, String> {
+    let deps_dir = Path::new(env!("BIGNUM_RUNTIME_DEPS_DIR"));
+    // every build of the crate leaves its own hashed archive, the newest is ours
+    let lib = fs::read_dir(deps_dir)
+        .map_err(|e| format!("can't list {}: {}", deps_dir.display(), e))?
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            let name = e.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("libbignum_runtime-") && name.ends_with(".a")
+        })
+        .max_by_key(|e| e.metadata().and_then(|m| m.modified()).ok())
+        .ok_or_else(|| {
+            format!(
+                "the bignum runtime library is missing from {}",
+                deps_dir.display()
+            )
+        })?;
+    Ok(vec![
+        lib.path().display().to_string(),
+        "-lpthread".to_owned(),
+        "-ldl".to_owned(),
+        "-lm".to_owned(),
+    ])
+}
+
+fn codegen_opt_level(opt_level: u32) -> LLVMCodeGenOptLevel {
+    match opt_level {
+        0 => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+        1 => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+        2 => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+        _ => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
     }
 }
 
-fn writing_dump(file_name: &str, module: LLVMModuleRef) {
-    unsafe {
-        let llvm_ir_ptr = LLVMPrintModuleToString(module);
-        let llvm_ir = CStr::from_ptr(llvm_ir_ptr as *const _);
-        match File::create(&file_name) {
-            Ok(mut f) => match f.write_all(llvm_ir.to_bytes()) {
-                Ok(_) => {}
-                Err(e) => println!(
-                    "The file '{}' can't be written because of the error: {}",
-                    file_name, e
-                ),
-            },
-            Err(e) => println!(
-                "The file '{}' can't be created because of the error: {}",
-                file_name, e
-            ),
+/// How `link` makes executables.
+#[derive(Default)]
+pub struct Linker<'a> {
+    /// LLVM installation whose `clang` is used instead of `cc`.
+    pub llvm_prefix: Option<&'a Path>,
+    /// Target triple, passed on to `clang` only.
+    pub triple: Option<&'a str>,
+    /// Libraries linked besides the runtime.
+    pub libs: &'a [String],
+    /// Directories searched for `libs`, and for shared libraries when the
+    /// executable runs.
+    pub lib_paths: &'a [PathBuf],
+}
+
+/// Links the object file `object` with the runtime libraries into the
+/// executable `out_path`.
+pub fn link(object: &str, out_path: &str, linker: &Linker) -> bool {
+    let bignum_libs = match bignum_libs() {
+        Ok(libs) => libs,
+        Err(e) => {
+            error!("{}", e);
+            return false;
         }
+    };
+    let mut cc = match linker.llvm_prefix {
+        Some(prefix) => {
+            let mut clang = Command::new(prefix.join("bin").join("clang"));
+            if let Some(triple) = linker.triple {
+                clang.arg(format!("--target={}", triple));
+            }
+            clang
+        }
+        None => Command::new("cc"),
+    };
+    cc.arg(object)
+        .arg(concat!(env!("CALC_RUNTIME_LIB_DIR"), "/libcalc_input.a"))
+        .args(bignum_libs);
+    for dir in linker.lib_paths {
+        // the loader resolves relative paths from the working directory
+        let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+        cc.arg(format!("-L{}", dir.display()))
+            .arg(format!("-Wl,-rpath,{}", dir.display()));
+    }
+    cc.args(linker.libs.iter().map(|lib| format!("-l{}", lib)))
+        .arg("-o")
+        .arg(out_path);
+    debug!("linking with {:?}", cc);
+    let cc = match cc.output() {
+        Ok(cc) => cc,
+        Err(e) => {
+            error!("can't run the linker {:?}: {}", cc, e);
+            return false;
+        }
+    };
+    debug!("linker exited with {}", cc.status);
+    if !cc.stdout.is_empty() {
+        info!("{}", String::from_utf8_lossy(&cc.stdout));
+    }
+    let stderr = String::from_utf8_lossy(&cc.stderr);
+    if !cc.status.success() {
+        error!("linking failed ({}): {}", cc.status, stderr);
+        return false;
+    }
+    if !stderr.is_empty() {
+        warn!("{}", stderr);
     }
+    true
 }
@@ -0,0 +1,75 @@
+//! Logger writing the diagnostics of the generator to the standard error,
+//! which keeps the standard output to the programs it runs.
+
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() <= Level::Warn {
+            if let Some(ref mut captured) = *CAPTURED.lock().unwrap() {
+                captured.push((record.level(), record.args().to_string()));
+            }
+        }
+        if record.level() as usize > SHOWN.load(Ordering::Relaxed) {
+            return;
+        }
+        let stderr = io::stderr();
+        let mut stderr = stderr.lock();
+        let _ = match record.level() {
+            Level::Error => writeln!(stderr, "error: {}", record.args()),
+            Level::Warn => writeln!(stderr, "warning: {}", record.args()),
+            _ => writeln!(stderr, "{}", record.args()),
+        };
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Most verbose level shown, as a `LevelFilter`.
+static SHOWN: AtomicUsize = AtomicUsize::new(0);
+
+/// Errors and warnings kept since `capture`, even if not shown.
+static CAPTURED: Mutex<Option<Vec<(Level, String)>>> = Mutex::new(None);
+
+/// Installs the logger, showing the messages up to `level`.
+pub fn init(level: LevelFilter) {
+    // fails only when a logger is installed already
+    let _ = log::set_logger(&LOGGER);
+    SHOWN.store(level as usize, Ordering::Relaxed);
+    // warnings still reach the logger to be captured
+    log::set_max_level(level.max(LevelFilter::Warn));
+}
+
+/// Starts keeping the errors and warnings logged, for `captured`.
+pub fn capture() {
+    *CAPTURED.lock().unwrap() = Some(vec![]);
+}
+
+/// Errors and warnings logged since `capture`, which stops keeping them.
+pub fn captured() -> Vec<(Level, String)> {
+    CAPTURED.lock().unwrap().take().unwrap_or_default()
+}
+
+/// Level of the messages shown for `-q` and the number of `-v` given.
+pub fn level(quiet: bool, verbosity: u64) -> LevelFilter {
+    match (quiet, verbosity) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, 2) => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
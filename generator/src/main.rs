@@ -1,35 +1,433 @@
+#[cfg(feature = "rust-bignum")]
+extern crate bignum_runtime;
+#[macro_use]
+extern crate clap;
+#[macro_use]
+extern crate lalrpop_util;
 extern crate libc;
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate serde_derive;
+#[cfg(test)]
+#[macro_use]
+extern crate quickcheck;
 
+pub mod ast;
+pub mod bignum;
 pub mod bignumloader;
+pub mod config;
 pub mod generator;
+pub mod input;
 pub mod llvm;
+pub mod logger;
+pub mod mir;
+pub mod report;
 
+lalrpop_mod!(#[allow(clippy::all)] pub calc);
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use config::Config;
 use generator::*;
+use std::path::{Path, PathBuf};
 use std::*;
 
 fn main() {
-    let argument = env::args().last().unwrap_or_default();
-    if argument == "exec" {
-        run_exec();
-    } else if argument == "compile" {
-        run_compile();
+    let matches = app().get_matches();
+    let sub_matches = matches.subcommand().1.unwrap_or(&matches);
+    logger::init(logger::level(
+        sub_matches.is_present("quiet"),
+        sub_matches.occurrences_of("verbose"),
+    ));
+    let options = match options(&matches) {
+        Ok(options) => options,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(-1)
+        }
+    };
+    info!("{:?}", options);
+    match matches.subcommand() {
+        ("run", Some(sub)) => run_exec(Some(read_program(sub)), &program_args(sub), &options),
+        ("build", Some(sub)) => {
+            let path = sub.value_of("FILE").unwrap();
+            let out_path = match sub.value_of("output") {
+                Some(out_path) => out_path.to_owned(),
+                None => default_output_path(path),
+            };
+            run_build(&read_program(sub), &out_path, &options)
+        }
+        ("emit", Some(sub)) => {
+            let emit: Vec<Emit> = sub
+                .values_of("emit")
+                .unwrap()
+                .map(|kind| Emit::parse(kind).unwrap())
+                .collect();
+            let out_name = out_name(sub.value_of("FILE").unwrap());
+            run_emit(&read_program(sub), &out_name, &emit, &options)
+        }
+        ("exec", Some(sub)) => {
+            let source = sub.value_of("FILE").map(|_| read_program(sub));
+            run_exec(source, &program_args(sub), &options)
+        }
+        ("compile", Some(sub)) => {
+            let source = sub.value_of("FILE").map(|_| read_program(sub));
+            let out_path = match sub.value_of("output") {
+                Some(out_path) => out_path.to_owned(),
+                None => options.out_dir.join("out").to_string_lossy().into_owned(),
+            };
+            run_compile(source, sub.is_present("test-lib"), &out_path, &options)
+        }
+        ("repl", Some(sub)) => run_repl(&program_args(sub), &options),
+        ("bench", Some(sub)) => {
+            let iterations = value_t!(sub, "iterations", u64).unwrap_or_else(|e| e.exit());
+            run_bench(iterations, &options)
+        }
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let file =
+        Arg::with_name("FILE").help("Program in the calculator language, see src/calc.lalrpop");
+    let output = Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .value_name("PATH")
+        .help("Path of the executable");
+    let args = Arg::with_name("ARGS")
+        .multiple(true)
+        .allow_hyphen_values(true)
+        .help("Arguments given to the program, see `argc` and `arg`");
+    let operands = Arg::with_name("operands")
+        .long("operands")
+        .value_name("SOURCE")
+        .validator(|s| Operands::parse(&s).map(|_| ()))
+        .help(
+            "Where the built in demo reads its numbers: stdin, args, file:<path>, \
+             or the numbers themselves separated by a comma; stdin by default",
+        )
+        .conflicts_with("FILE");
+    let demo_args = Arg::with_name("demo-args")
+        .long("demo-args")
+        .value_name("ARG")
+        .multiple(true)
+        .allow_hyphen_values(true)
+        .conflicts_with("FILE")
+        .help("Arguments given to the built in demo, the rest of the command line");
+    App::new("llvm-sys-playground")
+        .about("Compiles programs of a small calculator language with LLVM")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .args(&global_args())
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Runs a program with the JIT, exiting with its status")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(file.clone().required(true))
+                .arg(args.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("build")
+                .about("Builds an executable of a program, named after it by default")
+                .arg(file.clone().required(true))
+                .arg(output.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("emit")
+                .about("Writes the compiler's output for a program to the output directory")
+                .arg(file.clone().required(true))
+                .arg(
+                    Arg::with_name("emit")
+                        .long("emit")
+                        .value_name("KIND")
+                        .use_delimiter(true)
+                        .multiple(true)
+                        .possible_values(&Emit::NAMES)
+                        .default_value("llvm-ir")
+                        .help("Kinds of output to write"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("exec")
+                .about("Runs a program with the JIT, the built in demo without FILE")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(operands.clone())
+                .arg(demo_args)
+                .arg(file.clone())
+                .arg(args.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("compile")
+                .about("Builds `out` in the output directory of a program, the built in demo without FILE")
+                .arg(operands)
+                .arg(
+                    Arg::with_name("test-lib")
+                        .long("test-lib")
+                        .conflicts_with_all(&["FILE", "operands"])
+                        .help("Builds the demo calling into test-lib, linked with --lib test_lib"),
+                )
+                .arg(file.clone())
+                .arg(output.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("repl")
+                .about("Runs programs typed line by line with the JIT")
+                .setting(AppSettings::TrailingVarArg)
+                .arg(args.clone()),
+        )
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("Times arithmetic on bignums against hybrid integers")
+                .arg(
+                    Arg::with_name("iterations")
+                        .long("iterations")
+                        .value_name("N")
+                        .default_value("1000000")
+                        .help("Iterations of each workload"),
+                ),
+        )
+}
+
+/// Options accepted before or after any subcommand. The ones also set by
+/// the project file have no default here.
+fn global_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("config")
+            .long("config")
+            .value_name("PATH")
+            .env("CALC_CONFIG")
+            .global(true)
+            .help("Project file, Calc.toml when it exists by default"),
+        Arg::with_name("opt-level")
+            .short("O")
+            .long("opt-level")
+            .value_name("LEVEL")
+            .possible_values(&["0", "1", "2", "3"])
+            .env("CALC_OPT_LEVEL")
+            .global(true)
+            .help("LLVM optimization level, 0 by default"),
+        Arg::with_name("target")
+            .long("target")
+            .value_name("TRIPLE")
+            .env("CALC_TARGET")
+            .global(true)
+            .help("Target triple of compiled code, the host's by default"),
+        Arg::with_name("out-dir")
+            .long("out-dir")
+            .value_name("DIR")
+            .env("CALC_OUT_DIR")
+            .global(true)
+            .help("Directory of the generated files, ./target by default"),
+        Arg::with_name("dump").long("dump").global(true).help(
+            "Writes the MIR and LLVM IR of programs run with the JIT to the output directory",
+        ),
+        Arg::with_name("report")
+            .long("report")
+            .value_name("PATH")
+            .global(true)
+            .help("Writes a JSON report of what builds of executables produce to PATH"),
+        Arg::with_name("llvm-prefix")
+            .long("llvm-prefix")
+            .value_name("DIR")
+            .env("CALC_LLVM_PREFIX")
+            .global(true)
+            .help("LLVM installation whose clang links executables instead of cc"),
+        Arg::with_name("lib")
+            .short("l")
+            .long("lib")
+            .value_name("NAME")
+            .multiple(true)
+            .number_of_values(1)
+            .global(true)
+            .help("Library linked into executables besides the runtime"),
+        Arg::with_name("lib-path")
+            .short("L")
+            .long("lib-path")
+            .value_name("DIR")
+            .multiple(true)
+            .number_of_values(1)
+            .global(true)
+            .help("Directory searched for libraries"),
+        Arg::with_name("input-radix")
+            .long("input-radix")
+            .value_name("RADIX")
+            .default_value("auto")
+            .validator(|s| RadixConfig::parse_input(&s).map(|_| ()))
+            .global(true)
+            .help("Radix of numbers read, 2 to 64; auto also accepts 0x, 0o and 0b prefixes"),
+        Arg::with_name("output-radix")
+            .long("output-radix")
+            .value_name("RADIX")
+            .default_value("10")
+            .validator(|s| RadixConfig::parse_output(&s).map(|_| ()))
+            .global(true)
+            .help("Radix of numbers printed, 2 to 64"),
+        Arg::with_name("verbose")
+            .short("v")
+            .long("verbose")
+            .multiple(true)
+            .global(true)
+            .help("Logs more of what the generator does, repeat for details"),
+        Arg::with_name("quiet")
+            .short("q")
+            .long("quiet")
+            .conflicts_with("verbose")
+            .global(true)
+            .help("Only logs errors"),
+    ]
+}
+
+/// Options of the subcommand in `matches`, already validated by `app`, over
+/// the ones of the project file. Libraries and their directories add up.
+fn options(matches: &ArgMatches) -> Result<Options, String> {
+    let matches = match matches.subcommand() {
+        (_, Some(sub)) => sub,
+        _ => matches,
+    };
+    let config = Config::find(matches.value_of("config").map(Path::new))?;
+    let values = |name| -> Vec<String> {
+        matches
+            .values_of(name)
+            .map_or(vec![], |values| values.map(str::to_owned).collect())
+    };
+    let defaults = Options::default();
+    let mut libs = config.build.libs;
+    libs.extend(values("lib"));
+    let mut lib_paths = config.build.lib_paths;
+    lib_paths.extend(values("lib-path").into_iter().map(PathBuf::from));
+    Ok(Options {
+        radix: RadixConfig {
+            input: RadixConfig::parse_input(matches.value_of("input-radix").unwrap())?,
+            output: RadixConfig::parse_output(matches.value_of("output-radix").unwrap())?,
+        },
+        backend: defaults.backend,
+        opt_level: match matches.value_of("opt-level") {
+            Some(level) => level.parse().unwrap(),
+            None => config.build.opt_level.unwrap_or(defaults.opt_level),
+        },
+        target: matches
+            .value_of("target")
+            .map(str::to_owned)
+            .or(config.build.target),
+        libs,
+        lib_paths,
+        llvm_prefix: matches
+            .value_of("llvm-prefix")
+            .map(PathBuf::from)
+            .or(config.llvm.prefix),
+        out_dir: matches
+            .value_of("out-dir")
+            .map(PathBuf::from)
+            .or(config.build.out_dir)
+            .unwrap_or(defaults.out_dir),
+        dump: matches.is_present("dump"),
+        report: matches.value_of("report").map(PathBuf::from),
+        operands: match matches.value_of("operands") {
+            Some(operands) => Operands::parse(operands)?,
+            None => defaults.operands,
+        },
+    })
+}
+
+/// Contents of the `FILE` argument of `matches`.
+fn read_program(matches: &ArgMatches) -> String {
+    let path = matches.value_of("FILE").unwrap();
+    match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(e) => {
+            error!("can't read {}: {}", path, e);
+            std::process::exit(-1)
+        }
+    }
+}
+
+/// Command line of the program run for `matches`: the program file
+/// followed by the `ARGS`, or the generator itself followed by the
+/// `--demo-args` for the built in demo.
+fn program_args(matches: &ArgMatches) -> Vec<String> {
+    let mut args = match matches.value_of("FILE") {
+        Some(file) => vec![file.to_owned()],
+        None => vec![env::args().next().unwrap_or_default()],
+    };
+    let given = ["ARGS", "demo-args"]
+        .iter()
+        .flat_map(|&name| matches.values_of(name).into_iter().flatten());
+    args.extend(given.map(str::to_owned));
+    args
+}
+
+/// Name of the program file without its extension, in the current directory.
+fn default_output_path(program_path: &str) -> String {
+    path::Path::new(program_path)
+        .file_stem()
+        .map_or("out".to_owned(), |stem| stem.to_string_lossy().into_owned())
+}
+
+/// Name the generated files get for the program or executable `path`.
+fn out_name(path: &str) -> String {
+    path::Path::new(path)
+        .file_stem()
+        .map_or("output".to_owned(), |stem| {
+            stem.to_string_lossy().into_owned()
+        })
+}
+
+fn run_exec(source: Option<String>, args: &[String], options: &Options) {
+    let status = match source {
+        Some(source) => llvm_exec_program(&source, options, args),
+        None => llvm_exec(options, args),
+    };
+    std::process::exit(status.unwrap_or(-1))
+}
+
+fn run_compile(source: Option<String>, test_lib: bool, out_path: &str, options: &Options) {
+    let ok = match source {
+        Some(source) => llvm_compile_program(&source, "output", out_path, options),
+        None if test_lib => llvm_compile("output", out_path, options),
+        None => llvm_compile2("output", out_path, options),
+    };
+    if ok {
+        std::process::exit(0)
+    } else {
+        std::process::exit(-1)
+    }
+}
+
+fn run_build(source: &str, out_path: &str, options: &Options) {
+    if llvm_compile_program(source, &out_name(out_path), out_path, options) {
+        std::process::exit(0)
     } else {
-        println!("expected one of exec or compile arguments");
+        std::process::exit(-1)
     }
 }
 
-fn run_exec() {
-    if llvm_exec() {
+fn run_emit(source: &str, out_name: &str, emit: &[Emit], options: &Options) {
+    if llvm_emit_program(source, out_name, emit, options) {
         std::process::exit(0)
     } else {
         std::process::exit(-1)
     }
 }
 
-fn run_compile() {
-    if llvm_compile2("output") {
+fn run_repl(args: &[String], options: &Options) {
+    if llvm_repl(options, args) {
         std::process::exit(0)
     } else {
         std::process::exit(-1)
     }
 }
+
+fn run_bench(iterations: u64, options: &Options) {
+    let timings = match llvm_bench(iterations, options) {
+        Some(timings) => timings,
+        None => std::process::exit(-1),
+    };
+    println!("{} iterations", iterations);
+    for t in timings {
+        println!(
+            "{:<12} {:<7} {:>10.1} ns/iter",
+            t.workload, t.repr, t.ns_per_iter
+        );
+    }
+}
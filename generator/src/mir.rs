@@ -0,0 +1,566 @@
+//! Typed mid-level IR between the AST and code generation.
+//!
+//! Functions are made of basic blocks with explicit terminators. Every
+//! value lives in a typed local, operands being either locals or constants,
+//! so passes can work on it without knowing anything about LLVM.
+
+pub use ast::{BinOp, CmpOp, Type};
+use ast::{Program as AstProgram, SourceError};
+use bignumloader::BigInt;
+use std::fmt;
+
+mod build;
+mod fold;
+
+pub use self::build::Session;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Local(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Clone)]
+pub enum Const {
+    Int(i64),
+    BigInt(BigInt),
+    Str(String),
+    Bool(bool),
+}
+
+impl Const {
+    pub fn ty(&self) -> Type {
+        match *self {
+            Const::Int(_) => Type::Int,
+            Const::BigInt(_) => Type::BigInt,
+            Const::Str(_) => Type::Str,
+            Const::Bool(_) => Type::Bool,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Local(Local),
+    Const(Const),
+}
+
+#[derive(Debug, Clone)]
+pub enum Rvalue {
+    Use(Operand),
+    /// Both operands have the type of the destination.
+    Binary(BinOp, Operand, Operand),
+    Neg(Operand),
+    /// Comparison of two numbers of the same type or of two `bool`s.
+    Compare(CmpOp, Operand, Operand),
+    Not(Operand),
+    /// Conversion between numeric types, failing at run time when an
+    /// `int` can't hold the value.
+    Cast(Operand),
+    /// Number read from the standard input, always a `bigint`.
+    Read,
+    /// Number of command line arguments, an `int`.
+    Argc,
+    /// Command line argument at the `int` index, read as a `bigint`;
+    /// failing at run time when there is no such argument.
+    Arg(Operand),
+}
+
+#[derive(Debug, Clone)]
+pub enum Inst {
+    Assign(Local, Rvalue),
+    /// Calls a function of the program, storing what it returns in the
+    /// local. Arguments have the types of the parameters.
+    Call(Option<Local>, String, Vec<Operand>),
+    Print(Operand),
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator {
+    Return,
+    Goto(BlockId),
+    /// Goes to the first block when the `bool` operand is true.
+    Branch(Operand, BlockId, BlockId),
+    Unreachable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub insts: Vec<Inst>,
+    pub term: Terminator,
+}
+
+/// Where the value of a local lives.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Storage {
+    /// In the frame of the function.
+    Stack,
+    /// In a global with the given symbol, which the program defines.
+    Global(String),
+    /// In a global defined by an earlier program of the session.
+    Extern(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalDecl {
+    pub ty: Type,
+    /// Source name of a variable, temporaries have none.
+    pub name: Option<String>,
+    pub storage: Storage,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    /// Locals receiving the arguments.
+    pub params: Vec<Local>,
+    /// Local to assign before returning, for a function returning a value.
+    pub ret: Option<Local>,
+    pub locals: Vec<LocalDecl>,
+    /// Execution starts at the first block.
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub functions: Vec<Function>,
+}
+
+impl Function {
+    pub fn local_type(&self, local: Local) -> Type {
+        self.locals[local.0].ty
+    }
+
+    pub fn operand_type(&self, operand: &Operand) -> Type {
+        match *operand {
+            Operand::Local(local) => self.local_type(local),
+            Operand::Const(ref c) => c.ty(),
+        }
+    }
+
+    pub fn param_types(&self) -> Vec<Type> {
+        self.params.iter().map(|&p| self.local_type(p)).collect()
+    }
+
+    pub fn ret_type(&self) -> Option<Type> {
+        self.ret.map(|ret| self.local_type(ret))
+    }
+
+    /// Whether `local` is the destination of some instruction.
+    pub fn is_assigned(&self, local: Local) -> bool {
+        self.blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .any(|inst| match *inst {
+                Inst::Assign(dest, _) | Inst::Call(Some(dest), _, _) => dest == local,
+                _ => false,
+            })
+    }
+}
+
+/// Type checks `program` and translates it, `source` being the text it was
+/// parsed from to locate errors. `main` comes first in the result.
+pub fn build(source: &str, program: &AstProgram) -> Result<Program, SourceError> {
+    build::build_program(source, program, &mut Session::new())
+}
+
+/// Same as `build` for the next program of `session`, which can use the
+/// functions and top level variables of the earlier ones. Its top level
+/// statements go into a function named `main.<n>`.
+pub fn build_in_session(
+    source: &str,
+    program: &AstProgram,
+    session: &mut Session,
+) -> Result<Program, SourceError> {
+    session.programs += 1;
+    let res = build::build_program(source, program, session);
+    if res.is_err() {
+        session.programs -= 1;
+    }
+    res
+}
+
+/// Evaluates operations on constants at compile time.
+pub fn fold_constants(program: &mut Program) {
+    for func in &mut program.functions {
+        fold::fold_function(func);
+    }
+}
+
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Const::Int(v) => write!(f, "{}i", v),
+            Const::BigInt(ref v) => write!(f, "{}", v),
+            Const::Str(ref s) => write!(f, "{:?}", s),
+            Const::Bool(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Local(local) => write!(f, "_{}", local.0),
+            Operand::Const(ref c) => write!(f, "{}", c),
+        }
+    }
+}
+
+impl fmt::Display for Rvalue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rvalue::Use(ref a) => write!(f, "{}", a),
+            Rvalue::Binary(op, ref a, ref b) => write!(f, "{} {} {}", a, op.symbol(), b),
+            Rvalue::Neg(ref a) => write!(f, "-{}", a),
+            Rvalue::Compare(op, ref a, ref b) => write!(f, "{} {} {}", a, op.symbol(), b),
+            Rvalue::Not(ref a) => write!(f, "!{}", a),
+            Rvalue::Cast(ref a) => write!(f, "cast {}", a),
+            Rvalue::Read => write!(f, "read"),
+            Rvalue::Argc => write!(f, "argc"),
+            Rvalue::Arg(ref a) => write!(f, "arg {}", a),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let params: Vec<String> = self.params.iter().map(|p| format!("_{}", p.0)).collect();
+        write!(f, "fn {}({})", self.name, params.join(", "))?;
+        if let Some(ret) = self.ret {
+            write!(f, " -> _{}", ret.0)?;
+        }
+        writeln!(f, " {{")?;
+        for (i, local) in self.locals.iter().enumerate() {
+            write!(f, "    let _{}: {};", i, local.ty)?;
+            match (&local.name, &local.storage) {
+                (Some(name), Storage::Stack) => write!(f, " // {}", name)?,
+                (Some(name), Storage::Global(symbol)) => {
+                    write!(f, " // {}, global {}", name, symbol)?
+                }
+                (Some(name), Storage::Extern(symbol)) => {
+                    write!(f, " // {}, extern {}", name, symbol)?
+                }
+                _ => {}
+            }
+            writeln!(f)?;
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "  bb{}:", i)?;
+            for inst in &block.insts {
+                match *inst {
+                    Inst::Assign(local, ref rvalue) => {
+                        writeln!(f, "    _{} = {}", local.0, rvalue)?
+                    }
+                    Inst::Call(dest, ref name, ref args) => {
+                        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                        match dest {
+                            Some(dest) => write!(f, "    _{} = ", dest.0)?,
+                            None => write!(f, "    ")?,
+                        }
+                        writeln!(f, "{}({})", name, args.join(", "))?
+                    }
+                    Inst::Print(ref a) => writeln!(f, "    print {}", a)?,
+                }
+            }
+            match block.term {
+                Terminator::Return => writeln!(f, "    return")?,
+                Terminator::Goto(b) => writeln!(f, "    goto bb{}", b.0)?,
+                Terminator::Branch(ref c, t, e) => {
+                    writeln!(f, "    if {} goto bb{} else bb{}", c, t.0, e.0)?
+                }
+                Terminator::Unreachable => writeln!(f, "    unreachable")?,
+            }
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, func) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", func)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast;
+
+    fn lower(source: &str, fold: bool) -> Result<String, String> {
+        let program = ast::parse(source).map_err(|e| e.to_string())?;
+        let mut program = build(source, &program).map_err(|e| e.to_string())?;
+        if fold {
+            fold_constants(&mut program);
+        }
+        Ok(program.to_string())
+    }
+
+    #[test]
+    fn builds_typed_temporaries() {
+        assert_eq!(
+            lower("print read * (2 as int + 3 as int);", false).unwrap(),
+            "fn main() {\n    let _0: bigint;\n    let _1: int;\n    let _2: int;\n    \
+             let _3: int;\n    let _4: bigint;\n    let _5: bigint;\n  bb0:\n    _0 = read\n    \
+             _1 = cast 2\n    _2 = cast 3\n    _3 = _1 + _2\n    _4 = cast _3\n    \
+             _5 = _0 * _4\n    print _5\n    return\n}\n"
+        );
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(
+            lower("print read * (2 as int + 3 as int); print -(7 % 4);", true).unwrap(),
+            "fn main() {\n    let _0: bigint;\n    let _1: bigint;\n  bb0:\n    _0 = read\n    \
+             _1 = _0 * 5\n    print _1\n    print -3\n    return\n}\n"
+        );
+    }
+
+    #[test]
+    fn leaves_failing_operations_to_run_time() {
+        let folded = lower(
+            "print 1 / 0; print 9223372036854775807 as int + 1 as int; print (1 - 1) as int;",
+            true,
+        )
+        .unwrap();
+        assert!(folded.contains("_0 = 1 / 0"));
+        assert!(folded.contains("_1 = 9223372036854775807i + 1i"));
+        assert!(folded.contains("print 0i"));
+    }
+
+    #[test]
+    fn variables_and_scopes() {
+        let mir = lower(
+            "let x = read; let n: int = 1; { let x = x * 2; n = n + 1; print x; } print x;",
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            mir,
+            "fn main() {\n    let _0: bigint; // x\n    let _1: int; // n\n    \
+             let _2: bigint; // x\n  bb0:\n    _0 = read\n    _1 = 1i\n    \
+             _2 = _0 * 2\n    _1 = _1 + 1i\n    print _2\n    print _0\n    return\n}\n"
+        );
+    }
+
+    #[test]
+    fn name_errors() {
+        assert_eq!(
+            lower("let x = 1;\n{ print y; }", false).unwrap_err(),
+            "2:9: undefined variable `y`"
+        );
+        assert_eq!(
+            lower("let x = 1; { let x = 2; } let x = 3;", false).unwrap_err(),
+            "1:31: `x` is already defined in this scope"
+        );
+        assert_eq!(
+            lower("let x = x;", false).unwrap_err(),
+            "1:9: undefined variable `x`"
+        );
+        assert_eq!(
+            lower("{ let x = 1; } x = 2;", false).unwrap_err(),
+            "1:16: undefined variable `x`"
+        );
+        assert_eq!(
+            lower("let b = true; b = 1;", false).unwrap_err(),
+            "1:19: expected bool, found bigint"
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let mir = lower(
+            "print f(2 as int, read);\nfn f(a: int, b: bigint) -> bigint { return a * b; }",
+            true,
+        )
+        .unwrap();
+        assert_eq!(
+            mir,
+            "fn main() {\n    let _0: bigint;\n    let _1: bigint;\n  bb0:\n    _0 = read\n    \
+             _1 = f(2i, _0)\n    print _1\n    return\n}\n\n\
+             fn f(_0, _1) -> _2 {\n    let _0: int; // a\n    let _1: bigint; // b\n    \
+             let _2: bigint;\n    let _3: bigint;\n  bb0:\n    _3 = cast _0\n    \
+             _2 = _3 * _1\n    return\n  bb1:\n    unreachable\n}\n"
+        );
+    }
+
+    #[test]
+    fn exit_status_and_arguments() {
+        assert_eq!(
+            lower("if argc < 2 { return 2; }\nprint arg(1);", true).unwrap(),
+            "fn main() -> _2 {\n    let _0: int;\n    let _1: bool;\n    let _2: int;\n    \
+             let _3: bigint;\n  bb0:\n    _2 = 0i\n    _0 = argc\n    _1 = _0 < 2i\n    \
+             if _1 goto bb1 else bb2\n  bb1:\n    _2 = 2i\n    return\n  bb2:\n    goto bb3\n  \
+             bb3:\n    _3 = arg 1i\n    print _3\n    return\n  bb4:\n    goto bb3\n}\n"
+        );
+        assert_eq!(
+            lower("print 1;\nreturn;", true).unwrap(),
+            "fn main() {\n  bb0:\n    print 1\n    return\n  bb1:\n    return\n}\n"
+        );
+        assert_eq!(
+            lower("return true;", true).unwrap_err(),
+            "1:8: expected int, found bool"
+        );
+        assert_eq!(
+            lower("fn f() { return argc; }", true).unwrap_err(),
+            "1:17: function `f` doesn't return a value"
+        );
+    }
+
+    #[test]
+    fn function_errors() {
+        let errors = [
+            ("g();", "1:1: undefined function `g`"),
+            (
+                "fn f() {}\nfn f() {}",
+                "2:4: function `f` is already defined",
+            ),
+            ("fn main() {}", "1:4: function `main` is already defined"),
+            (
+                "fn f(a: int, a: int) {}",
+                "1:14: `a` is already defined in this scope",
+            ),
+            (
+                "fn f(a: int) {}\nf();",
+                "2:1: `f` takes 1 arguments but 0 were given",
+            ),
+            ("fn f(a: int) {}\nf(true);", "2:3: expected int, found bool"),
+            (
+                "fn f() {}\nprint f();",
+                "2:7: function `f` doesn't return a value",
+            ),
+            (
+                "fn f() { return 1; }",
+                "1:17: function `f` doesn't return a value",
+            ),
+            (
+                "fn f() -> int { return; }",
+                "1:17: expected a return value of type int",
+            ),
+            (
+                "fn f() -> int { print 1; }",
+                "1:26: function `f` may end without returning a value",
+            ),
+            (
+                "fn f() -> int { let x = y; }",
+                "1:25: undefined variable `y`",
+            ),
+        ];
+        for &(source, error) in errors.iter() {
+            assert_eq!(lower(source, false).unwrap_err(), error, "{}", source);
+        }
+    }
+
+    #[test]
+    fn short_circuit() {
+        let mir = lower("let a = read; print a > 0 && a % 2 == 1;", true).unwrap();
+        assert_eq!(
+            mir,
+            "fn main() {\n    let _0: bigint; // a\n    let _1: bool;\n    let _2: bool;\n    \
+             let _3: bigint;\n  bb0:\n    _0 = read\n    _2 = _0 > 0\n    _1 = _2\n    \
+             if _1 goto bb1 else bb2\n  bb1:\n    _3 = _0 % 2\n    _1 = _3 == 1\n    \
+             goto bb2\n  bb2:\n    print _1\n    return\n}\n"
+        );
+    }
+
+    #[test]
+    fn folds_constant_branches() {
+        let mir = lower("if 1 < 2 as int { print 1; } else { print 2; }", true).unwrap();
+        assert!(mir.contains("  bb0:\n    goto bb1\n"), "{}", mir);
+    }
+
+    #[test]
+    fn control_flow_errors() {
+        let errors = [
+            ("break;", "1:1: not inside of a loop"),
+            (
+                "fn f() { continue; }\nwhile true { f(); }",
+                "1:10: not inside of a loop",
+            ),
+            ("if 1 { }", "1:4: expected bool, found bigint"),
+            ("while !2 { }", "1:8: expected bool, found bigint"),
+            ("print true && 1;", "1:15: expected bool, found bigint"),
+            (
+                "print \"a\" < \"b\";",
+                "1:7: `<` can't be applied to string and string",
+            ),
+            (
+                "print true < false;",
+                "1:7: `<` can't be applied to bool and bool",
+            ),
+            (
+                "for i in 0..true { }",
+                "1:10: `..` can't be applied to bigint and bool",
+            ),
+            ("for i in 0..1 { }\nprint i;", "2:7: undefined variable `i`"),
+            (
+                "fn f(n: int) -> int { if n > 0 { return 1; } }",
+                "1:46: function `f` may end without returning a value",
+            ),
+        ];
+        for &(source, error) in errors.iter() {
+            assert_eq!(lower(source, false).unwrap_err(), error, "{}", source);
+        }
+        let returns = "fn f(n: int) -> int { if n > 0 { return 1; } else { return 2; } }";
+        assert!(lower(returns, false).is_ok());
+    }
+
+    #[test]
+    fn type_errors() {
+        assert_eq!(
+            lower("print 1;\nprint 2 * \"x\";", false).unwrap_err(),
+            "2:7: `*` can't be applied to bigint and string"
+        );
+        assert_eq!(
+            lower("print -true;", false).unwrap_err(),
+            "1:7: can't negate a bool"
+        );
+        assert_eq!(
+            lower("print \"1\" as int;", false).unwrap_err(),
+            "1:7: can't cast string to int"
+        );
+    }
+
+    #[test]
+    fn sessions() {
+        let mut session = Session::new();
+        let mut lower_next = |source: &str| -> Result<String, String> {
+            let program = ast::parse(source).map_err(|e| e.to_string())?;
+            let mut program =
+                build_in_session(source, &program, &mut session).map_err(|e| e.to_string())?;
+            fold_constants(&mut program);
+            Ok(program.to_string())
+        };
+        assert_eq!(
+            lower_next("let x = 2 as int;\nfn f() -> int { return 3 as int; }").unwrap(),
+            "fn main.1() {\n    let _0: int; // x, global calc.x.1\n  bb0:\n    _0 = 2i\n    \
+             return\n}\n\nfn f() -> _0 {\n    let _0: int;\n  bb0:\n    _0 = 3i\n    return\n  \
+             bb1:\n    unreachable\n}\n"
+        );
+        assert_eq!(
+            lower_next("print x;").unwrap(),
+            "fn main.2() {\n    let _0: int; // x, extern calc.x.1\n  bb0:\n    print _0\n    \
+             return\n}\n"
+        );
+        assert_eq!(
+            lower_next("fn f() {}").unwrap_err(),
+            "1:4: function `f` is already defined"
+        );
+        assert_eq!(
+            lower_next("print y;").unwrap_err(),
+            "1:7: undefined variable `y`"
+        );
+        assert_eq!(
+            lower_next("{ let y = 1; }\nx = x + f();\nprint x;").unwrap(),
+            "fn main.3() {\n    let _0: int; // x, extern calc.x.1\n    let _1: bigint; // y\n    \
+             let _2: int;\n  bb0:\n    _1 = 1\n    _2 = f()\n    _0 = _0 + _2\n    print _0\n    \
+             return\n}\n"
+        );
+    }
+}
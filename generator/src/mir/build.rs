@@ -0,0 +1,644 @@
+use super::*;
+use ast::{Expr, FnDecl, Ident, LogicOp, Span, Stmt};
+use std::collections::HashMap;
+
+/// Parameter and return types of a function.
+#[derive(Clone)]
+struct Signature {
+    params: Vec<Type>,
+    ret: Option<Type>,
+}
+
+/// Functions and top level variables of the programs built so far in a
+/// session, see `build_in_session`.
+#[derive(Default)]
+pub struct Session {
+    signatures: HashMap<String, Signature>,
+    /// Type and symbol of the global holding each variable.
+    globals: HashMap<String, (Type, String)>,
+    /// Number of programs in the session, zero outside of one.
+    pub(super) programs: usize,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+}
+
+pub fn build_program(
+    source: &str,
+    program: &AstProgram,
+    session: &mut Session,
+) -> Result<Program, SourceError> {
+    let mut signatures = session.signatures.clone();
+    for decl in &program.functions {
+        let name = &decl.name;
+        if name.name == "main" || signatures.contains_key(&name.name) {
+            return Err(SourceError::new(
+                source,
+                name.span,
+                format!("function `{}` is already defined", name.name),
+            ));
+        }
+        let signature = Signature {
+            params: decl.params.iter().map(|p| p.ty).collect(),
+            ret: decl.ret,
+        };
+        signatures.insert(name.name.clone(), signature);
+    }
+
+    let mut functions = vec![];
+    for decl in &program.functions {
+        functions.push(build_function(source, &signatures, decl)?);
+    }
+    let main = if session.programs == 0 {
+        let mut builder = FunctionBuilder::new(source, &signatures, "main");
+        builder.block(&program.stmts)?;
+        builder.terminate(Terminator::Return);
+        builder.func
+    } else {
+        let name = format!("main.{}", session.programs);
+        let mut builder = FunctionBuilder::new(source, &signatures, &name);
+        for (name, &(ty, ref symbol)) in &session.globals {
+            builder.func.locals.push(LocalDecl {
+                ty,
+                name: Some(name.clone()),
+                storage: Storage::Extern(symbol.clone()),
+            });
+            let local = Local(builder.func.locals.len() - 1);
+            builder.scopes[0].insert(name.clone(), local);
+        }
+        // variables declared at the top level outlive the program
+        builder.globals_scope = Some((builder.scopes.len() + 1, session.programs));
+        builder.block(&program.stmts)?;
+        builder.terminate(Terminator::Return);
+        builder.func
+    };
+
+    for local in &main.locals {
+        if let (Some(name), Storage::Global(symbol)) = (&local.name, &local.storage) {
+            session
+                .globals
+                .insert(name.clone(), (local.ty, symbol.clone()));
+        }
+    }
+    session.signatures = signatures;
+    functions.insert(0, main);
+    Ok(Program { functions })
+}
+
+fn build_function(
+    source: &str,
+    signatures: &HashMap<String, Signature>,
+    decl: &FnDecl,
+) -> Result<Function, SourceError> {
+    let mut builder = FunctionBuilder::new(source, signatures, &decl.name.name);
+    for param in &decl.params {
+        let local = builder.declare(&param.name, param.ty)?;
+        builder.func.params.push(local);
+    }
+    if let Some(ty) = decl.ret {
+        builder.func.ret = Some(builder.new_temp(ty));
+    }
+    builder.block(&decl.body)?;
+    if builder.func.ret.is_none() {
+        builder.terminate(Terminator::Return);
+    } else if builder.is_reachable(builder.current) {
+        let end = Span::new(decl.span.end - 1, decl.span.end);
+        return Err(builder.error(
+            end,
+            format!(
+                "function `{}` may end without returning a value",
+                decl.name.name
+            ),
+        ));
+    }
+    Ok(builder.func)
+}
+
+struct FunctionBuilder<'s> {
+    source: &'s str,
+    signatures: &'s HashMap<String, Signature>,
+    func: Function,
+    current: BlockId,
+    /// Variables visible from the current block, innermost scope last.
+    scopes: Vec<HashMap<String, Local>>,
+    /// Blocks `continue` and `break` go to in the enclosing loops.
+    loops: Vec<(BlockId, BlockId)>,
+    /// Depth of the scope whose variables are globals of the session, with
+    /// the number of the program to tell them from the ones of other programs.
+    globals_scope: Option<(usize, usize)>,
+}
+
+impl<'s> FunctionBuilder<'s> {
+    fn new(source: &'s str, signatures: &'s HashMap<String, Signature>, name: &str) -> Self {
+        FunctionBuilder {
+            source,
+            signatures,
+            func: Function {
+                name: name.to_owned(),
+                params: vec![],
+                ret: None,
+                locals: vec![],
+                blocks: vec![Block {
+                    insts: vec![],
+                    term: Terminator::Unreachable,
+                }],
+            },
+            current: BlockId(0),
+            scopes: vec![HashMap::new()],
+            loops: vec![],
+            globals_scope: None,
+        }
+    }
+
+    fn error(&self, span: Span, message: String) -> SourceError {
+        SourceError::new(self.source, span, message)
+    }
+
+    fn new_temp(&mut self, ty: Type) -> Local {
+        self.func.locals.push(LocalDecl {
+            ty,
+            name: None,
+            storage: Storage::Stack,
+        });
+        Local(self.func.locals.len() - 1)
+    }
+
+    fn push(&mut self, inst: Inst) {
+        self.func.blocks[self.current.0].insts.push(inst);
+    }
+
+    fn terminate(&mut self, term: Terminator) {
+        self.func.blocks[self.current.0].term = term;
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.func.blocks.push(Block {
+            insts: vec![],
+            term: Terminator::Unreachable,
+        });
+        BlockId(self.func.blocks.len() - 1)
+    }
+
+    /// Continues in a new block, which the caller links to the others.
+    fn start_block(&mut self) -> BlockId {
+        self.current = self.new_block();
+        self.current
+    }
+
+    fn is_reachable(&self, block: BlockId) -> bool {
+        let mut seen = vec![false; self.func.blocks.len()];
+        let mut pending = vec![BlockId(0)];
+        while let Some(id) = pending.pop() {
+            if seen[id.0] {
+                continue;
+            }
+            seen[id.0] = true;
+            match self.func.blocks[id.0].term {
+                Terminator::Goto(next) => pending.push(next),
+                Terminator::Branch(_, then, els) => {
+                    pending.push(then);
+                    pending.push(els);
+                }
+                Terminator::Return | Terminator::Unreachable => {}
+            }
+        }
+        seen[block.0]
+    }
+
+    /// Temporary of type `ty` assigned `rvalue`.
+    fn assign_temp(&mut self, ty: Type, rvalue: Rvalue) -> Operand {
+        let temp = self.new_temp(ty);
+        self.push(Inst::Assign(temp, rvalue));
+        Operand::Local(temp)
+    }
+
+    fn lookup(&self, ident: &Ident) -> Result<Local, SourceError> {
+        self.scopes
+            .iter()
+            .rev()
+            .filter_map(|scope| scope.get(&ident.name))
+            .next()
+            .cloned()
+            .ok_or_else(|| self.error(ident.span, format!("undefined variable `{}`", ident.name)))
+    }
+
+    fn declare(&mut self, ident: &Ident, ty: Type) -> Result<Local, SourceError> {
+        if self
+            .scopes
+            .last()
+            .is_some_and(|scope| scope.contains_key(&ident.name))
+        {
+            return Err(self.error(
+                ident.span,
+                format!("`{}` is already defined in this scope", ident.name),
+            ));
+        }
+        let storage = match self.globals_scope {
+            Some((depth, program)) if depth == self.scopes.len() => {
+                Storage::Global(format!("calc.{}.{}", ident.name, program))
+            }
+            _ => Storage::Stack,
+        };
+        self.func.locals.push(LocalDecl {
+            ty,
+            name: Some(ident.name.clone()),
+            storage,
+        });
+        let local = Local(self.func.locals.len() - 1);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(ident.name.clone(), local);
+        }
+        Ok(local)
+    }
+
+    /// `value` of the expression at `span` as a `ty`, which may need a
+    /// conversion between numeric types.
+    fn coerce(&mut self, value: Operand, ty: Type, span: Span) -> Result<Operand, SourceError> {
+        let from = self.func.operand_type(&value);
+        if from != ty && !(from.is_numeric() && ty.is_numeric()) {
+            return Err(self.error(span, format!("expected {}, found {}", ty, from)));
+        }
+        Ok(self.convert(value, ty))
+    }
+
+    /// Stores `value` into the variable `var`.
+    fn assign(&mut self, var: Local, value: Operand, span: Span) -> Result<(), SourceError> {
+        let ty = self.func.local_type(var);
+        let value = self.coerce(value, ty, span)?;
+        // computing straight into the variable saves a copy
+        if let Operand::Local(temp) = value {
+            let insts = &mut self.func.blocks[self.current.0].insts;
+            if self.func.locals[temp.0].name.is_none() {
+                if let Some(&mut Inst::Assign(ref mut dest, _)) = insts.last_mut() {
+                    if *dest == temp {
+                        *dest = var;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+        self.push(Inst::Assign(var, Rvalue::Use(value)));
+        Ok(())
+    }
+
+    /// Makes `main` return its exit status, 0 until a `return` sets it.
+    fn declare_exit_status(&mut self) {
+        let status = self.new_temp(Type::Int);
+        let init = Inst::Assign(status, Rvalue::Use(Operand::Const(Const::Int(0))));
+        self.func.blocks[0].insts.insert(0, init);
+        self.func.ret = Some(status);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> Result<(), SourceError> {
+        match *stmt {
+            Stmt::Print(ref expr, _) => {
+                let value = self.expr(expr)?;
+                self.push(Inst::Print(value));
+            }
+            Stmt::Let(ref ident, ty, ref expr) => {
+                let value = self.expr(expr)?;
+                let ty = ty.unwrap_or_else(|| self.func.operand_type(&value));
+                // the variable is only visible after its initializer
+                let var = self.declare(ident, ty)?;
+                self.assign(var, value, expr.span())?;
+            }
+            Stmt::Assign(ref ident, ref expr) => {
+                let var = self.lookup(ident)?;
+                let value = self.expr(expr)?;
+                self.assign(var, value, expr.span())?;
+            }
+            Stmt::Block(ref stmts) => self.block(stmts)?,
+            Stmt::If(ref cond, ref then, ref els) => {
+                let cond = self.condition(cond)?;
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+                let end_block = self.new_block();
+                self.terminate(Terminator::Branch(cond, then_block, else_block));
+                self.current = then_block;
+                self.block(then)?;
+                self.terminate(Terminator::Goto(end_block));
+                self.current = else_block;
+                self.block(els)?;
+                self.terminate(Terminator::Goto(end_block));
+                self.current = end_block;
+            }
+            Stmt::While(ref cond, ref body) => {
+                let head_block = self.new_block();
+                let body_block = self.new_block();
+                let end_block = self.new_block();
+                self.terminate(Terminator::Goto(head_block));
+                self.current = head_block;
+                let cond = self.condition(cond)?;
+                self.terminate(Terminator::Branch(cond, body_block, end_block));
+                self.current = body_block;
+                self.loop_body(body, head_block, end_block)?;
+                self.terminate(Terminator::Goto(head_block));
+                self.current = end_block;
+            }
+            Stmt::For(ref var, ref from, ref to, ref body) => self.for_loop(var, from, to, body)?,
+            Stmt::Break(span) | Stmt::Continue(span) => {
+                let (continue_block, break_block) = match self.loops.last() {
+                    Some(&targets) => targets,
+                    None => {
+                        return Err(self.error(span, "not inside of a loop".to_owned()));
+                    }
+                };
+                let target = match *stmt {
+                    Stmt::Break(_) => break_block,
+                    _ => continue_block,
+                };
+                self.terminate(Terminator::Goto(target));
+                // anything following the jump is unreachable
+                self.start_block();
+            }
+            Stmt::Return(ref value, span) => {
+                if self.func.ret.is_none() && value.is_some() && self.func.name == "main" {
+                    self.declare_exit_status();
+                }
+                match (self.func.ret, value) {
+                    (Some(ret), Some(expr)) => {
+                        let value = self.expr(expr)?;
+                        self.assign(ret, value, expr.span())?;
+                    }
+                    (None, None) => {}
+                    // `main` keeps the exit status set so far
+                    (Some(_), None) if self.func.name == "main" => {}
+                    (Some(ret), None) => {
+                        let ty = self.func.local_type(ret);
+                        return Err(
+                            self.error(span, format!("expected a return value of type {}", ty))
+                        );
+                    }
+                    (None, Some(expr)) => {
+                        return Err(self.error(
+                            expr.span(),
+                            format!("function `{}` doesn't return a value", self.func.name),
+                        ));
+                    }
+                }
+                self.terminate(Terminator::Return);
+                // anything following the return is unreachable
+                self.start_block();
+            }
+            Stmt::Expr(Expr::Call(ref ident, ref args, span)) => {
+                self.call(ident, args, span)?;
+            }
+            Stmt::Expr(ref expr) => {
+                self.expr(expr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds `stmts` in a scope of their own.
+    fn block(&mut self, stmts: &[Stmt]) -> Result<(), SourceError> {
+        self.scopes.push(HashMap::new());
+        let res = stmts.iter().try_for_each(|stmt| self.stmt(stmt));
+        self.scopes.pop();
+        res
+    }
+
+    fn loop_body(
+        &mut self,
+        body: &[Stmt],
+        continue_block: BlockId,
+        break_block: BlockId,
+    ) -> Result<(), SourceError> {
+        self.loops.push((continue_block, break_block));
+        let res = self.block(body);
+        self.loops.pop();
+        res
+    }
+
+    /// `for var in from..to { body }`, the bounds being evaluated once.
+    /// The loop counts with a hidden variable, so assigning `var` in the
+    /// body doesn't change the number of iterations.
+    fn for_loop(
+        &mut self,
+        var: &Ident,
+        from: &Expr,
+        to: &Expr,
+        body: &[Stmt],
+    ) -> Result<(), SourceError> {
+        let from_value = self.expr(from)?;
+        let to_value = self.expr(to)?;
+        let span = Span::new(from.span().start, to.span().end);
+        let (ty, from_value, to_value) = self.numeric_operands("..", from_value, to_value, span)?;
+        let counter = self.new_temp(ty);
+        self.push(Inst::Assign(counter, Rvalue::Use(from_value)));
+        let end = self.new_temp(ty);
+        self.push(Inst::Assign(end, Rvalue::Use(to_value)));
+
+        let head_block = self.new_block();
+        let body_block = self.new_block();
+        let step_block = self.new_block();
+        let end_block = self.new_block();
+        self.terminate(Terminator::Goto(head_block));
+        self.current = head_block;
+        let more = self.assign_temp(
+            Type::Bool,
+            Rvalue::Compare(CmpOp::Lt, Operand::Local(counter), Operand::Local(end)),
+        );
+        self.terminate(Terminator::Branch(more, body_block, end_block));
+
+        self.current = body_block;
+        self.scopes.push(HashMap::new());
+        let res = self.declare(var, ty).and_then(|var| {
+            self.push(Inst::Assign(var, Rvalue::Use(Operand::Local(counter))));
+            self.loop_body(body, step_block, end_block)
+        });
+        self.scopes.pop();
+        res?;
+        self.terminate(Terminator::Goto(step_block));
+
+        self.current = step_block;
+        let one = match ty {
+            Type::Int => Const::Int(1),
+            _ => Const::BigInt(BigInt::from_i64(1).expect("bignum library failure")),
+        };
+        let next = Rvalue::Binary(BinOp::Add, Operand::Local(counter), Operand::Const(one));
+        self.push(Inst::Assign(counter, next));
+        self.terminate(Terminator::Goto(head_block));
+        self.current = end_block;
+        Ok(())
+    }
+
+    /// `bool` operand holding the value of `cond`.
+    fn condition(&mut self, cond: &Expr) -> Result<Operand, SourceError> {
+        let value = self.expr(cond)?;
+        self.coerce(value, Type::Bool, cond.span())
+    }
+
+    /// Converts the operands of an arithmetic operation or of a comparison
+    /// at `span` to their common numeric type, which is returned with them.
+    fn numeric_operands(
+        &mut self,
+        symbol: &str,
+        lhs: Operand,
+        rhs: Operand,
+        span: Span,
+    ) -> Result<(Type, Operand, Operand), SourceError> {
+        let lhs_ty = self.func.operand_type(&lhs);
+        let rhs_ty = self.func.operand_type(&rhs);
+        if !lhs_ty.is_numeric() || !rhs_ty.is_numeric() {
+            return Err(self.error(
+                span,
+                format!("`{}` can't be applied to {} and {}", symbol, lhs_ty, rhs_ty),
+            ));
+        }
+        // an int mixed with a bigint is widened, unless the bigint
+        // is a literal which fits into an int
+        let ty = if (lhs_ty == Type::Int && (rhs_ty == Type::Int || fits_int(&rhs)))
+            || (rhs_ty == Type::Int && fits_int(&lhs))
+        {
+            Type::Int
+        } else {
+            Type::BigInt
+        };
+        let lhs = self.convert(lhs, ty);
+        let rhs = self.convert(rhs, ty);
+        Ok((ty, lhs, rhs))
+    }
+
+    /// Calls the function `ident`, returning the local holding its result
+    /// unless it returns nothing.
+    fn call(
+        &mut self,
+        ident: &Ident,
+        args: &[Expr],
+        span: Span,
+    ) -> Result<Option<Local>, SourceError> {
+        let signatures = self.signatures;
+        let signature = match signatures.get(&ident.name) {
+            Some(signature) => signature,
+            None => {
+                return Err(self.error(ident.span, format!("undefined function `{}`", ident.name)))
+            }
+        };
+        if args.len() != signature.params.len() {
+            return Err(self.error(
+                span,
+                format!(
+                    "`{}` takes {} arguments but {} were given",
+                    ident.name,
+                    signature.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        let mut values = vec![];
+        for (arg, &ty) in args.iter().zip(&signature.params) {
+            let value = self.expr(arg)?;
+            values.push(self.coerce(value, ty, arg.span())?);
+        }
+        let dest = signature.ret.map(|ty| self.new_temp(ty));
+        self.push(Inst::Call(dest, ident.name.clone(), values));
+        Ok(dest)
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Operand, SourceError> {
+        match *expr {
+            Expr::Num(ref value, _) => Ok(Operand::Const(Const::BigInt(value.clone()))),
+            Expr::Str(ref s, _) => Ok(Operand::Const(Const::Str(s.clone()))),
+            Expr::Bool(b, _) => Ok(Operand::Const(Const::Bool(b))),
+            Expr::Var(ref ident) => Ok(Operand::Local(self.lookup(ident)?)),
+            Expr::Read(_) => Ok(self.assign_temp(Type::BigInt, Rvalue::Read)),
+            Expr::Argc(_) => Ok(self.assign_temp(Type::Int, Rvalue::Argc)),
+            Expr::Arg(ref index, _) => {
+                let value = self.expr(index)?;
+                let value = self.coerce(value, Type::Int, index.span())?;
+                Ok(self.assign_temp(Type::BigInt, Rvalue::Arg(value)))
+            }
+            Expr::Call(ref ident, ref args, span) => match self.call(ident, args, span)? {
+                Some(dest) => Ok(Operand::Local(dest)),
+                None => Err(self.error(
+                    span,
+                    format!("function `{}` doesn't return a value", ident.name),
+                )),
+            },
+            Expr::Neg(ref operand, span) => {
+                let value = self.expr(operand)?;
+                let ty = self.func.operand_type(&value);
+                if !ty.is_numeric() {
+                    return Err(self.error(span, format!("can't negate a {}", ty)));
+                }
+                Ok(self.assign_temp(ty, Rvalue::Neg(value)))
+            }
+            Expr::Binary(op, ref lhs, ref rhs, span) => {
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                let (ty, lhs, rhs) = self.numeric_operands(op.symbol(), lhs, rhs, span)?;
+                Ok(self.assign_temp(ty, Rvalue::Binary(op, lhs, rhs)))
+            }
+            Expr::Compare(op, ref lhs, ref rhs, span) => {
+                let lhs = self.expr(lhs)?;
+                let rhs = self.expr(rhs)?;
+                let lhs_ty = self.func.operand_type(&lhs);
+                let rhs_ty = self.func.operand_type(&rhs);
+                let bools = lhs_ty == Type::Bool && rhs_ty == Type::Bool;
+                let (lhs, rhs) = if bools && (op == CmpOp::Eq || op == CmpOp::Ne) {
+                    (lhs, rhs)
+                } else {
+                    let (_, lhs, rhs) = self.numeric_operands(op.symbol(), lhs, rhs, span)?;
+                    (lhs, rhs)
+                };
+                Ok(self.assign_temp(Type::Bool, Rvalue::Compare(op, lhs, rhs)))
+            }
+            Expr::Not(ref operand, _) => {
+                let value = self.condition(operand)?;
+                Ok(self.assign_temp(Type::Bool, Rvalue::Not(value)))
+            }
+            Expr::Logic(op, ref lhs, ref rhs, _) => {
+                // the right operand is only evaluated when the left one
+                // doesn't decide the result
+                let res = self.new_temp(Type::Bool);
+                let lhs = self.condition(lhs)?;
+                self.push(Inst::Assign(res, Rvalue::Use(lhs)));
+                let rhs_block = self.new_block();
+                let end_block = self.new_block();
+                let cond = Operand::Local(res);
+                self.terminate(match op {
+                    LogicOp::And => Terminator::Branch(cond, rhs_block, end_block),
+                    LogicOp::Or => Terminator::Branch(cond, end_block, rhs_block),
+                });
+                self.current = rhs_block;
+                let rhs_span = rhs.span();
+                let rhs = self.expr(rhs)?;
+                self.assign(res, rhs, rhs_span)?;
+                self.terminate(Terminator::Goto(end_block));
+                self.current = end_block;
+                Ok(Operand::Local(res))
+            }
+            Expr::Cast(ref operand, ty, span) => {
+                let value = self.expr(operand)?;
+                let from = self.func.operand_type(&value);
+                if from == ty {
+                    Ok(value)
+                } else if from.is_numeric() && ty.is_numeric() {
+                    Ok(self.convert(value, ty))
+                } else {
+                    Err(self.error(span, format!("can't cast {} to {}", from, ty)))
+                }
+            }
+        }
+    }
+
+    /// `value` converted to the numeric type `ty`.
+    fn convert(&mut self, value: Operand, ty: Type) -> Operand {
+        if self.func.operand_type(&value) == ty {
+            value
+        } else {
+            self.assign_temp(ty, Rvalue::Cast(value))
+        }
+    }
+}
+
+fn fits_int(operand: &Operand) -> bool {
+    match *operand {
+        Operand::Const(Const::BigInt(ref value)) => value.to_i64().is_some(),
+        _ => false,
+    }
+}
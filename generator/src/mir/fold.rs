@@ -0,0 +1,234 @@
+use super::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+pub fn fold_function(func: &mut Function) {
+    loop {
+        let evaluated = evaluate_constant_rvalues(func);
+        let propagated = propagate_constant_temps(func);
+        if !evaluated && !propagated {
+            break;
+        }
+    }
+    fold_constant_branches(func);
+    remove_unused_locals(func);
+}
+
+/// Replaces branches on a constant by a jump to the block taken.
+fn fold_constant_branches(func: &mut Function) {
+    for block in &mut func.blocks {
+        let target = match block.term {
+            Terminator::Branch(Operand::Const(Const::Bool(cond)), then, els) => {
+                if cond {
+                    then
+                } else {
+                    els
+                }
+            }
+            _ => continue,
+        };
+        block.term = Terminator::Goto(target);
+    }
+}
+
+/// Replaces operations on constants by their result.
+fn evaluate_constant_rvalues(func: &mut Function) -> bool {
+    let mut changed = false;
+    let locals = &func.locals;
+    for block in &mut func.blocks {
+        for inst in &mut block.insts {
+            if let Inst::Assign(local, ref mut rvalue) = *inst {
+                if let Some(value) = evaluate(rvalue, locals[local.0].ty) {
+                    *rvalue = Rvalue::Use(Operand::Const(value));
+                    changed = true;
+                }
+            }
+        }
+    }
+    changed
+}
+
+/// Result of `rvalue` when it can be computed now. Operations which fail,
+/// like a division by zero, are left for the generated code to report.
+fn evaluate(rvalue: &Rvalue, ty: Type) -> Option<Const> {
+    match *rvalue {
+        Rvalue::Neg(Operand::Const(Const::Int(v))) => v.checked_neg().map(Const::Int),
+        Rvalue::Neg(Operand::Const(Const::BigInt(ref v))) => {
+            v.checked_neg().ok().map(Const::BigInt)
+        }
+        Rvalue::Binary(op, Operand::Const(Const::Int(a)), Operand::Const(Const::Int(b))) => {
+            let res = match op {
+                BinOp::Add => a.checked_add(b),
+                BinOp::Sub => a.checked_sub(b),
+                BinOp::Mul => a.checked_mul(b),
+                BinOp::Div => a.checked_div(b),
+                BinOp::Rem => a.checked_rem(b),
+            };
+            res.map(Const::Int)
+        }
+        Rvalue::Binary(
+            op,
+            Operand::Const(Const::BigInt(ref a)),
+            Operand::Const(Const::BigInt(ref b)),
+        ) => {
+            let res = match op {
+                BinOp::Add => a.checked_add(b),
+                BinOp::Sub => a.checked_sub(b),
+                BinOp::Mul => a.checked_mul(b),
+                BinOp::Div => a.checked_div(b),
+                BinOp::Rem => a.checked_rem(b),
+            };
+            res.ok().map(Const::BigInt)
+        }
+        Rvalue::Compare(op, Operand::Const(ref a), Operand::Const(ref b)) => {
+            let ordering = match (a, b) {
+                (Const::Int(a), Const::Int(b)) => a.cmp(b),
+                (Const::BigInt(a), Const::BigInt(b)) => a.cmp(b),
+                (Const::Bool(a), Const::Bool(b)) => a.cmp(b),
+                _ => return None,
+            };
+            let res = match op {
+                CmpOp::Eq => ordering == Ordering::Equal,
+                CmpOp::Ne => ordering != Ordering::Equal,
+                CmpOp::Lt => ordering == Ordering::Less,
+                CmpOp::Le => ordering != Ordering::Greater,
+                CmpOp::Gt => ordering == Ordering::Greater,
+                CmpOp::Ge => ordering != Ordering::Less,
+            };
+            Some(Const::Bool(res))
+        }
+        Rvalue::Not(Operand::Const(Const::Bool(b))) => Some(Const::Bool(!b)),
+        Rvalue::Cast(Operand::Const(Const::Int(v))) if ty == Type::BigInt => {
+            BigInt::from_i64(v).ok().map(Const::BigInt)
+        }
+        Rvalue::Cast(Operand::Const(Const::BigInt(ref v))) if ty == Type::Int => {
+            v.to_i64().map(Const::Int)
+        }
+        _ => None,
+    }
+}
+
+/// Substitutes temporaries assigned a constant exactly once with it and
+/// drops their assignments.
+fn propagate_constant_temps(func: &mut Function) -> bool {
+    let mut assignments: HashMap<Local, usize> = HashMap::new();
+    for block in &func.blocks {
+        for inst in &block.insts {
+            match *inst {
+                Inst::Assign(local, _) | Inst::Call(Some(local), _, _) => {
+                    *assignments.entry(local).or_insert(0) += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    let mut consts: HashMap<Local, Const> = HashMap::new();
+    for block in &func.blocks {
+        for inst in &block.insts {
+            if let Inst::Assign(local, Rvalue::Use(Operand::Const(ref c))) = *inst {
+                if func.locals[local.0].name.is_none()
+                    && func.ret != Some(local)
+                    && assignments[&local] == 1
+                {
+                    consts.insert(local, c.clone());
+                }
+            }
+        }
+    }
+    if consts.is_empty() {
+        return false;
+    }
+    for block in &mut func.blocks {
+        block.insts.retain(|inst| match *inst {
+            Inst::Assign(local, _) => !consts.contains_key(&local),
+            _ => true,
+        });
+        for_each_operand(block, &mut |operand| {
+            if let Operand::Local(local) = *operand {
+                if let Some(c) = consts.get(&local) {
+                    *operand = Operand::Const(c.clone());
+                }
+            }
+        });
+    }
+    true
+}
+
+/// Drops locals which are neither assigned nor read, renumbering the others.
+fn remove_unused_locals(func: &mut Function) {
+    let mut used = vec![false; func.locals.len()];
+    for &local in func.params.iter().chain(&func.ret) {
+        used[local.0] = true;
+    }
+    for block in &mut func.blocks {
+        for inst in &block.insts {
+            match *inst {
+                Inst::Assign(local, _) | Inst::Call(Some(local), _, _) => used[local.0] = true,
+                _ => {}
+            }
+        }
+        for_each_operand(block, &mut |operand| {
+            if let Operand::Local(local) = *operand {
+                used[local.0] = true;
+            }
+        });
+    }
+    let mut remap = vec![None; func.locals.len()];
+    let mut locals = vec![];
+    for (i, local) in func.locals.drain(..).enumerate() {
+        if used[i] {
+            remap[i] = Some(Local(locals.len()));
+            locals.push(local);
+        }
+    }
+    func.locals = locals;
+    let renumber = |local: Local| remap[local.0].expect("unused local referenced");
+    for param in &mut func.params {
+        *param = renumber(*param);
+    }
+    func.ret = func.ret.map(renumber);
+    for block in &mut func.blocks {
+        for inst in &mut block.insts {
+            match *inst {
+                Inst::Assign(ref mut local, _) | Inst::Call(Some(ref mut local), _, _) => {
+                    *local = renumber(*local)
+                }
+                _ => {}
+            }
+        }
+        for_each_operand(block, &mut |operand| {
+            if let Operand::Local(ref mut local) = *operand {
+                *local = renumber(*local);
+            }
+        });
+    }
+}
+
+fn for_each_operand(block: &mut Block, f: &mut dyn FnMut(&mut Operand)) {
+    for inst in &mut block.insts {
+        match *inst {
+            Inst::Assign(_, ref mut rvalue) => match *rvalue {
+                Rvalue::Use(ref mut a)
+                | Rvalue::Neg(ref mut a)
+                | Rvalue::Not(ref mut a)
+                | Rvalue::Cast(ref mut a)
+                | Rvalue::Arg(ref mut a) => f(a),
+                Rvalue::Binary(_, ref mut a, ref mut b)
+                | Rvalue::Compare(_, ref mut a, ref mut b) => {
+                    f(a);
+                    f(b);
+                }
+                Rvalue::Read | Rvalue::Argc => {}
+            },
+            Inst::Call(_, _, ref mut args) => {
+                for a in args {
+                    f(a);
+                }
+            }
+            Inst::Print(ref mut a) => f(a),
+        }
+    }
+    if let Terminator::Branch(ref mut cond, _, _) = block.term {
+        f(cond);
+    }
+}
@@ -0,0 +1,128 @@
+//! Build report written with `--report`: what a build of an executable
+//! produced and how long it took, as JSON for tools tracking the generator.
+//!
+//! ```json
+//! {
+//!   "success": true,
+//!   "target": { "triple": "x86_64-pc-linux-gnu", "cpu": "skylake", "features": "..." },
+//!   "opt-level": 2,
+//!   "timings-ms": { "ir-build": 1.2, "verify": 0.1, "optimize": 3.4, "codegen": 5.6, "link": 40.2 },
+//!   "object-size": 4096,
+//!   "executable-size": 20480,
+//!   "functions": 3,
+//!   "instructions": 120,
+//!   "diagnostics": [{ "level": "warning", "message": "..." }]
+//! }
+//! ```
+
+extern crate serde_json;
+
+use llvm::TargetInfo;
+use log::Level;
+use logger;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Report {
+    pub success: bool,
+    pub target: Option<TargetInfo>,
+    pub opt_level: u32,
+    #[serde(rename = "timings-ms")]
+    pub timings: Timings,
+    /// Sizes in bytes of the files written.
+    pub object_size: Option<u64>,
+    pub executable_size: Option<u64>,
+    /// Functions defined by the program and their LLVM instructions, after
+    /// optimization.
+    pub functions: usize,
+    pub instructions: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Milliseconds taken by each phase of the build, the ones not reached
+/// being left out.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Timings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ir_build: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verify: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optimize: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codegen: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostic {
+    pub level: String,
+    pub message: String,
+}
+
+impl Report {
+    /// Starts a report for a build at `opt_level`, keeping the errors and
+    /// warnings logged from now on.
+    pub fn start(opt_level: u32) -> Report {
+        logger::capture();
+        Report {
+            opt_level,
+            ..Report::default()
+        }
+    }
+
+    /// Ends the report of a build which succeeded or not, with the
+    /// diagnostics logged since `start`.
+    pub fn finish(&mut self, success: bool) {
+        self.success = success;
+        self.diagnostics = logger::captured()
+            .into_iter()
+            .map(|(level, message)| Diagnostic {
+                level: match level {
+                    Level::Error => "error",
+                    _ => "warning",
+                }
+                .to_owned(),
+                message,
+            })
+            .collect();
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        writeln!(file)
+    }
+}
+
+/// Runs `phase`, recording how long it took in `duration`.
+pub fn time<T, F: FnOnce() -> T>(duration: &mut Option<f64>, phase: F) -> T {
+    let start = Instant::now();
+    let result = phase();
+    *duration = Some(start.elapsed().as_secs_f64() * 1000.0);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_reports() {
+        let mut report = Report::start(2);
+        report.timings.ir_build = Some(1.5);
+        report.finish(false);
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["opt-level"], 2);
+        assert_eq!(json["target"], serde_json::Value::Null);
+        assert_eq!(json["timings-ms"], serde_json::json!({ "ir-build": 1.5 }));
+        assert_eq!(json["diagnostics"], serde_json::json!([]));
+    }
+}
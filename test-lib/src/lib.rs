@@ -54,4 +54,20 @@ pub extern fn create_test() -> Box<TestS>{
 pub extern fn create_slice() -> Box<[u32;3]>{
     let d = [1,2,3];
     Box::new(d)
-}
\ No newline at end of file
+}
+// cleanups also run on slots never filled, so both accept null
+#[no_mangle]
+pub extern fn free_test(test: *mut TestS){
+    if test.is_null() {
+        return;
+    }
+    unsafe { drop(Box::from_raw(test)) };
+}
+
+#[no_mangle]
+pub extern fn free_slice(slice: *mut [u32;3]){
+    if slice.is_null() {
+        return;
+    }
+    unsafe { drop(Box::from_raw(slice)) };
+}